@@ -0,0 +1,97 @@
+// compares the entity backed Board with the BitBoard used by bots and simulations
+//
+//     cargo bench --bench board
+
+use bevy::{ecs::entity::Entity, math::IVec2};
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use tetris::tetris_block::{reachable_placements, BitBoard, BlockName, Board, Cell};
+
+const WIDTH: usize = 10;
+const HEIGHT: usize = 20;
+
+// the bottom half of the board filled in, with one gap in each row
+fn messy_board() -> Board {
+    let mut board = Board::new(WIDTH, HEIGHT);
+    for row in 0..HEIGHT / 2 {
+        let gap = (row * 7) % WIDTH;
+        for col in (0..WIDTH).filter(|&col| col != gap) {
+            let pos = IVec2::new(col as i32, row as i32);
+            *board.cell_mut(pos) =
+                Some(Cell::garbage(Entity::from_raw((row * WIDTH + col) as u32)));
+        }
+    }
+    board
+}
+
+// the bottom rows completely filled in, ready to be cleared
+fn full_rows_board(rows: usize) -> Board {
+    let mut board = Board::new(WIDTH, HEIGHT);
+    for row in 0..rows {
+        for col in 0..WIDTH {
+            let pos = IVec2::new(col as i32, row as i32);
+            *board.cell_mut(pos) =
+                Some(Cell::garbage(Entity::from_raw((row * WIDTH + col) as u32)));
+        }
+    }
+    board
+}
+
+fn can_place(c: &mut Criterion) {
+    let board = messy_board();
+    let bits = BitBoard::from(&board);
+    let block = BlockName::T.create_movable((0, 0).into());
+    let positions: Vec<IVec2> = (0..HEIGHT as i32)
+        .flat_map(|y| (0..WIDTH as i32).map(move |x| IVec2::new(x, y)))
+        .collect();
+
+    let mut group = c.benchmark_group("can_place");
+    group.bench_function("board", |b| {
+        b.iter(|| {
+            positions
+                .iter()
+                .filter(|&&pos| board.can_place(&block.move_relative(pos)))
+                .count()
+        })
+    });
+    group.bench_function("bit_board", |b| {
+        b.iter(|| {
+            positions
+                .iter()
+                .filter(|&&pos| bits.can_place(&block.move_relative(pos)))
+                .count()
+        })
+    });
+    group.finish();
+}
+
+fn clear_lines(c: &mut Criterion) {
+    let board = full_rows_board(4);
+    let bits = BitBoard::from(&board);
+
+    let mut group = c.benchmark_group("clear_filled_lines");
+    group.bench_function("board", |b| {
+        b.iter(|| black_box(board.clone()).clear_filled_lines())
+    });
+    group.bench_function("bit_board", |b| {
+        b.iter(|| black_box(bits.clone()).clear_filled_lines())
+    });
+    group.finish();
+}
+
+fn placements(c: &mut Criterion) {
+    let board = messy_board();
+    let bits = BitBoard::from(&board);
+    let block = BlockName::T.create_movable(board.spawn_position());
+
+    let mut group = c.benchmark_group("reachable_placements");
+    group.bench_function("board", |b| {
+        b.iter(|| reachable_placements(black_box(&board), &block).len())
+    });
+    group.bench_function("bit_board", |b| {
+        b.iter(|| reachable_placements(black_box(&bits), &block).len())
+    });
+    group.finish();
+}
+
+criterion_group!(benches, can_place, clear_lines, placements);
+criterion_main!(benches);
@@ -0,0 +1,42 @@
+// plays random placements in the headless environment as fast as it can
+//
+//     cargo run --release --example headless
+
+use std::time::Instant;
+
+use rand::{thread_rng, Rng};
+use tetris::tetris_block::{Action, Env, EnvConfig};
+
+fn main() {
+    let mut env = Env::new(EnvConfig::default());
+    let mut rng = thread_rng();
+
+    let games = 1000;
+    let mut pieces = 0;
+    let mut lines = 0.;
+    let start = Instant::now();
+
+    for seed in 0..games {
+        let mut obs = env.reset(seed);
+        loop {
+            let idx = rng.gen_range(0..obs.placements.len());
+            let (next, reward, done) = env.step(Action::Place(idx));
+            pieces += 1;
+            lines += reward;
+            if done {
+                break;
+            }
+            obs = next;
+        }
+    }
+
+    let elapsed = start.elapsed().as_secs_f32();
+    println!(
+        "{} games ({} pieces, {} lines) in {:.2}s, {:.0} games/s",
+        games,
+        pieces,
+        lines,
+        elapsed,
+        games as f32 / elapsed
+    );
+}
@@ -0,0 +1,15 @@
+pub mod tetris_block;
+
+pub struct Dims {
+    pub width: i32,
+    pub height: i32,
+}
+pub const GRID_CELLS: Dims = Dims {
+    width: 8,
+    height: 12,
+};
+pub const CELL_SIDE_LEN: f32 = 40.;
+// space between the boards in split screen
+pub const BOARD_GAP: f32 = CELL_SIDE_LEN * 2.;
+// the panel to the right of each board with hold, next and the score
+pub const HUD_WIDTH: f32 = CELL_SIDE_LEN * 4.;
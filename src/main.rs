@@ -1,36 +1,160 @@
-mod tetris_block;
-
 use bevy::prelude::*;
 
-use tetris_block::*;
-
-pub struct Dims {
-    width: i32,
-    height: i32,
-}
-pub const GRID_CELLS: Dims = Dims {
-    width: 8,
-    height: 12,
-};
-pub const CELL_SIDE_LEN: f32 = 40.;
+use tetris::{tetris_block::*, BOARD_GAP, CELL_SIDE_LEN, HUD_WIDTH};
 
 fn main() {
-    App::new()
-        .insert_resource(WindowDescriptor {
-            width: GRID_CELLS.width as f32 * CELL_SIDE_LEN,
-            height: GRID_CELLS.height as f32 * CELL_SIDE_LEN,
-            title: "Knockoff Tetris".to_string(),
-            resizable: false,
-            decorations: true,
-            ..default()
+    let args: Vec<String> = std::env::args().collect();
+    let arg_value = |flag: &str| {
+        args.iter()
+            .position(|arg| arg == flag)
+            .map(|idx| args.get(idx + 1).cloned().unwrap_or_default())
+    };
+
+    let mut settings = Settings::load().unwrap_or_else(|err| panic!("bad settings, {}", err));
+
+    // `--animation <easing>` picks how blocks slide into place, `off` snaps them there
+    match arg_value("--animation").as_deref() {
+        None => {}
+        Some("off") => settings.animation = None,
+        Some(name) => {
+            settings.animation = Some(Easing::from_name(name).unwrap_or_else(|| {
+                panic!(
+                    "unknown animation {:?}, try off, linear, ease-out or ease-in-out",
+                    name
+                )
+            }))
+        }
+    }
+
+    // `--line-clear-delay <ms>` and `--entry-delay <ms>` set the pauses before the next
+    // block, `--clear-effect <flash|dissolve>` how cleared lines go
+    if let Some(ms) = arg_value("--line-clear-delay") {
+        settings.line_clear_delay_ms = ms.parse().expect("--line-clear-delay takes milliseconds");
+    }
+    if let Some(ms) = arg_value("--entry-delay") {
+        settings.entry_delay_ms = ms.parse().expect("--entry-delay takes milliseconds");
+    }
+    if let Some(name) = arg_value("--clear-effect") {
+        settings.clear_effect = ClearEffect::from_name(&name)
+            .unwrap_or_else(|| panic!("unknown clear effect {:?}, try flash or dissolve", name));
+    }
+
+    // `--host <port>` waits for someone to `--join <host:port>`, for versus online
+    let session = if let Some(port) = arg_value("--host") {
+        let port = port.parse().expect("--host takes a port number");
+        Some(Session::host(port, &settings).unwrap_or_else(|err| panic!("couldn't host: {}", err)))
+    } else {
+        arg_value("--join").map(|addr| {
+            Session::join(&addr, &settings)
+                .unwrap_or_else(|err| panic!("couldn't join {}: {}", addr, err))
         })
-        .insert_resource(ClearColor(Color::rgb(0.5, 0.5, 0.5)))
-        .add_startup_system(setup_camera)
-        .add_plugins(DefaultPlugins)
-        .add_plugin(TetrisBlockPlugin)
-        .run();
+    };
+
+    // `--resume` picks up the game saved on the way out last time (or with F5)
+    let resume = args
+        .iter()
+        .any(|arg| arg == "--resume")
+        .then(|| SavedGame::load().unwrap_or_else(|err| panic!("couldn't resume: {}", err)));
+
+    // `--versus` puts two boards side by side, for two players on one keyboard
+    let players = if let Some(saved) = &resume {
+        saved.players.len()
+    } else if session.is_some() || args.iter().any(|arg| arg == "--versus") {
+        2
+    } else {
+        1
+    };
+
+    // `--mode <name>` plays for something, rather than just playing
+    let mode_name = match &resume {
+        Some(saved) => Some(saved.mode.clone()),
+        None => arg_value("--mode"),
+    };
+    let mode: Box<dyn GameMode> = match mode_name {
+        None => Box::new(FreePlay),
+        Some(name) => mode_by_name(&name)
+            .unwrap_or_else(|| panic!("unknown mode {:?}, try {}", name, MODE_NAMES.join(", "))),
+    };
+
+    // anything set up from the command line goes straight into the game, rather than
+    // the main menu
+    let straight_in = [
+        "--mode",
+        "--resume",
+        "--host",
+        "--join",
+        "--puzzles",
+        "--fumen",
+        "--tbp",
+    ];
+    let start = if args.iter().any(|arg| straight_in.contains(&arg.as_str())) {
+        GameState::Playing
+    } else {
+        GameState::Menu
+    };
+
+    let mut app = App::new();
+    app.insert_resource(WindowDescriptor {
+        width: (settings.board_width as f32 * CELL_SIDE_LEN + HUD_WIDTH + BOARD_GAP)
+            * players as f32
+            - BOARD_GAP,
+        height: settings.board_height as f32 * CELL_SIDE_LEN,
+        title: "Knockoff Tetris".to_string(),
+        resizable: false,
+        decorations: true,
+        ..default()
+    })
+    .insert_resource(ClearColor(Color::rgb(0.5, 0.5, 0.5)))
+    .insert_resource(Mode(mode))
+    .insert_resource(settings.animation())
+    .insert_resource(settings.delays())
+    .insert_resource(settings.clone())
+    .add_startup_system(setup_camera)
+    .add_plugins(DefaultPlugins)
+    .add_plugin(TetrisBlockPlugin {
+        players,
+        seed: session.as_ref().map(|session| session.seed()),
+        remote_player: session.as_ref().map(|session| session.remote_player()),
+        start,
+    });
+    if let Some(session) = session {
+        app.add_plugin(NetPlugin { session });
+    }
+    app.add_plugin(SavePlugin { resume });
+
+    // `--puzzles <file or directory>` plays through puzzles instead of a mode
+    if let Some(path) = arg_value("--puzzles") {
+        let puzzles = load_puzzles(path.as_ref(), settings.board_width, settings.board_height)
+            .unwrap_or_else(|err| panic!("couldn't load puzzles: {}", err));
+        app.add_plugin(PuzzlePlugin { puzzles });
+    }
+
+    // `--fumen <fumen string>` starts the board off with the first page of a fumen
+    // diagram. F prints the board as one.
+    let import = arg_value("--fumen").map(|fumen| {
+        let pages = decode_fumen(&fumen).unwrap_or_else(|err| panic!("bad fumen: {}", err));
+        pages[0].clone()
+    });
+    app.add_plugin(FumenPlugin { import });
+
+    // `--spectate <port>` streams the game to anyone connecting on localhost
+    if let Some(port) = arg_value("--spectate") {
+        app.add_plugin(SpectatePlugin {
+            port: port.parse().expect("--spectate takes a port number"),
+        });
+    }
+
+    // `--tbp <program> [args...]` hands control of the game to an external bot
+    if let Some(idx) = args.iter().position(|arg| arg == "--tbp") {
+        app.add_plugin(TbpPlugin {
+            command: args[idx + 1..].to_vec(),
+        });
+    }
+
+    app.run();
 }
 
 fn setup_camera(mut commands: Commands) {
     commands.spawn_bundle(OrthographicCameraBundle::new_2d());
+    commands.spawn_bundle(UiCameraBundle::default());
 }
@@ -0,0 +1,349 @@
+use bevy::prelude::*;
+
+use super::{
+    add_cell_children,
+    bit_board::BitBoard,
+    board::Board,
+    cell_positioning::AbsolutePositionedPiece,
+    handle_block_actions, last_player,
+    movable_block::MovableBlock,
+    move_gen::{path_to, reachable_placements, sorted_cells, Placement},
+    piece_queue::PieceQueue,
+    skin::Skin,
+    BlockAction, CellStyle, Ghost, PlayerAction, TetrisBlock,
+};
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum AiMode {
+    Off,
+    // show where the ai would put the active block
+    Hint,
+    // the ai moves the active block itself
+    Play,
+}
+
+// how much each feature of the board contributes to its score, features that make
+// the board worse should get negative weights
+#[derive(Clone, Debug)]
+pub struct Weights {
+    pub aggregate_height: f32,
+    pub holes: f32,
+    pub bumpiness: f32,
+    pub completed_lines: f32,
+    pub wells: f32,
+}
+impl Default for Weights {
+    fn default() -> Self {
+        Weights {
+            aggregate_height: -0.51,
+            holes: -0.36,
+            bumpiness: -0.18,
+            completed_lines: 0.76,
+            wells: -0.1,
+        }
+    }
+}
+
+// on the player the ai plays as
+#[derive(Component)]
+pub struct Ai {
+    pub mode: AiMode,
+    pub weights: Weights,
+    // how many pieces from the next queue to take into account
+    pub lookahead: usize,
+    target: Option<MovableBlock>,
+    move_timer: Timer,
+}
+impl Default for Ai {
+    fn default() -> Self {
+        Ai {
+            mode: AiMode::Off,
+            weights: default(),
+            lookahead: 1,
+            target: None,
+            move_timer: Timer::from_seconds(0.1, true),
+        }
+    }
+}
+
+// marks the piece showing the placement the ai suggests
+#[derive(Component)]
+struct AiHint;
+
+pub struct AiPlugin;
+impl Plugin for AiPlugin {
+    fn build(&self, app: &mut App) {
+        if let Some(player) = last_player(&mut app.world) {
+            app.world.entity_mut(player).insert(Ai::default());
+        }
+        app.add_system(toggle_ai_mode)
+            .add_system_to_stage(
+                "update_block_positions",
+                plan_placement.before(handle_block_actions),
+            )
+            .add_system_to_stage(
+                "update_block_positions",
+                play_placement
+                    .after(plan_placement)
+                    .before(handle_block_actions),
+            );
+    }
+}
+
+// features of a board, used to judge how good a placement is
+#[derive(Debug, Default, PartialEq)]
+pub struct Features {
+    pub aggregate_height: i32,
+    pub holes: i32,
+    pub bumpiness: i32,
+    pub completed_lines: i32,
+    pub wells: i32,
+}
+impl Features {
+    pub fn of(board: &BitBoard, completed_lines: i32) -> Features {
+        let heights = board.column_heights();
+        let bumpiness = heights.windows(2).map(|w| (w[0] - w[1]).abs()).sum();
+
+        // walls count as full height columns, so a well along the edge still counts
+        let wall = board.height() as i32;
+        let wells = (0..heights.len())
+            .map(|col| {
+                let left = if col == 0 { wall } else { heights[col - 1] };
+                let right = heights.get(col + 1).copied().unwrap_or(wall);
+                (left.min(right) - heights[col]).max(0)
+            })
+            .sum();
+
+        Features {
+            aggregate_height: heights.iter().sum(),
+            holes: board.holes() as i32,
+            bumpiness,
+            completed_lines,
+            wells,
+        }
+    }
+
+    pub fn score(&self, weights: &Weights) -> f32 {
+        weights.aggregate_height * self.aggregate_height as f32
+            + weights.holes * self.holes as f32
+            + weights.bumpiness * self.bumpiness as f32
+            + weights.completed_lines * self.completed_lines as f32
+            + weights.wells * self.wells as f32
+    }
+}
+
+// places the block on a scratch copy of the board, returning the new board and the
+// number of lines cleared
+pub fn simulate_placement(board: &BitBoard, block: &MovableBlock) -> (BitBoard, i32) {
+    let mut board = board.clone();
+    let lines = board.lock(block) as i32;
+    (board, lines)
+}
+
+// picks the placement for `block` which leaves the best board once the `next` blocks
+// have been placed as well
+pub fn best_placement(
+    board: &BitBoard,
+    block: &MovableBlock,
+    next: &[MovableBlock],
+    weights: &Weights,
+) -> Option<Placement> {
+    search(board, block, next, weights, 0).map(|(_, placement)| placement)
+}
+
+fn search(
+    board: &BitBoard,
+    block: &MovableBlock,
+    next: &[MovableBlock],
+    weights: &Weights,
+    lines_so_far: i32,
+) -> Option<(f32, Placement)> {
+    let mut best: Option<(f32, Placement)> = None;
+
+    for placement in reachable_placements(board, block) {
+        let (board, lines) = simulate_placement(board, &placement.block);
+        let lines = lines_so_far + lines;
+
+        let score = match next.split_first() {
+            Some((next_block, rest)) => search(&board, next_block, rest, weights, lines)
+                .map_or(f32::NEG_INFINITY, |(score, _)| score),
+            None => Features::of(&board, lines).score(weights),
+        };
+
+        let is_better = match &best {
+            Some((best_score, _)) => score > *best_score,
+            None => true,
+        };
+        if is_better {
+            best = Some((score, placement));
+        }
+    }
+
+    best
+}
+
+fn toggle_ai_mode(
+    mut commands: Commands,
+    kb: Res<Input<KeyCode>>,
+    mut query: Query<&mut Ai>,
+    hint_query: Query<Entity, With<AiHint>>,
+) {
+    if !kb.just_pressed(KeyCode::B) {
+        return;
+    }
+
+    for mut ai in query.iter_mut() {
+        ai.mode = match ai.mode {
+            AiMode::Off => AiMode::Hint,
+            AiMode::Hint => AiMode::Play,
+            AiMode::Play => AiMode::Off,
+        };
+        println!("ai mode is now {:?}", ai.mode);
+
+        // force a new plan, which (re)spawns the hint if it's needed
+        ai.target = None;
+    }
+    for ent in hint_query.iter() {
+        commands.entity(ent).despawn_recursive();
+    }
+}
+
+// decides where a newly spawned active block should go
+fn plan_placement(
+    mut commands: Commands,
+    skin: Res<Skin>,
+    mut players: Query<(Entity, &mut Ai, &Board, &PieceQueue)>,
+    active_query: Query<(&Parent, &TetrisBlock), Without<Ghost>>,
+    new_block_query: Query<&Parent, (Added<TetrisBlock>, Without<Ghost>)>,
+    hint_query: Query<(Entity, &Parent), With<AiHint>>,
+) {
+    for (player, mut ai, board_state, piece_queue) in players.iter_mut() {
+        if ai.mode == AiMode::Off {
+            continue;
+        }
+
+        let active = match active_query.iter().find(|(parent, _)| parent.0 == player) {
+            Some((_, block)) => block,
+            None => continue,
+        };
+        let is_new = new_block_query.iter().any(|parent| parent.0 == player);
+        if ai.target.is_some() && !is_new {
+            continue;
+        }
+
+        let next: Vec<_> = piece_queue
+            .upcoming()
+            .take(ai.lookahead)
+            .map(|name| name.create_movable(board_state.spawn_position()))
+            .collect();
+        ai.target = best_placement(
+            &BitBoard::from(board_state),
+            &active.movable,
+            &next,
+            &ai.weights,
+        )
+        .map(|placement| placement.block);
+
+        for (ent, parent) in hint_query.iter() {
+            if parent.0 == player {
+                commands.entity(ent).despawn_recursive();
+            }
+        }
+
+        let target = match (&ai.target, ai.mode) {
+            (Some(target), AiMode::Hint) => target,
+            _ => continue,
+        };
+        let hint = commands
+            .spawn()
+            .insert_bundle(TransformBundle::identity())
+            .insert(AbsolutePositionedPiece {
+                pos: target.root_position(),
+                rot: target.rot(),
+                def: target.definition,
+            })
+            .with_children(|builder| {
+                add_cell_children(builder, &skin, Color::WHITE, CellStyle::Hint, target)
+            })
+            .insert(AiHint)
+            .id();
+        commands.entity(player).add_child(hint);
+    }
+}
+
+// sends the inputs which move the active block to where the ai wants it
+fn play_placement(
+    time: Res<Time>,
+    mut players: Query<(Entity, &mut Ai, &Board)>,
+    active_query: Query<(&Parent, &TetrisBlock), Without<Ghost>>,
+    mut actions: EventWriter<PlayerAction>,
+) {
+    for (player, mut ai, board_state) in players.iter_mut() {
+        if ai.mode != AiMode::Play || !ai.move_timer.tick(time.delta()).just_finished() {
+            continue;
+        }
+
+        let active = active_query.iter().find(|(parent, _)| parent.0 == player);
+        let (active, target) = match (active, &ai.target) {
+            (Some((_, active)), Some(target)) => (active, target),
+            _ => continue,
+        };
+
+        // find the path from wherever the block is now, as gravity may have moved it
+        // since the plan was made
+        let path = path_to(board_state, &active.movable, &sorted_cells(target));
+        let action = match path.as_ref().and_then(|placement| placement.moves.first()) {
+            Some(&mv) => BlockAction::Move(mv),
+            None if path.is_some() => BlockAction::HardDrop,
+            // the target can't be reached any more, plan again
+            None => {
+                ai.target = None;
+                continue;
+            }
+        };
+        actions.send(PlayerAction { player, action });
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::tetris_block::{bit_board::BitBoard, movable_block::BlockName};
+
+    use super::{best_placement, Features, Weights};
+
+    #[test]
+    fn test_features() {
+        // ..██....
+        // ██..██..
+        let mut board = BitBoard::new(4, 4);
+        for pos in [(0, 0), (2, 0), (1, 1)] {
+            board.set_occupied(pos.into());
+        }
+
+        assert_eq!(
+            Features::of(&board, 0),
+            Features {
+                aggregate_height: 4,
+                holes: 1,
+                bumpiness: 3,
+                completed_lines: 0,
+                wells: 2,
+            }
+        );
+    }
+
+    #[test]
+    fn test_fills_gap() {
+        // a single gap on the right, the I block should be stood up in it
+        let mut board = BitBoard::new(4, 8);
+        for col in 0..3 {
+            for row in 0..4 {
+                board.set_occupied((col, row).into());
+            }
+        }
+
+        let block = BlockName::I.create_movable((2, 6).into());
+        let placement = best_placement(&board, &block, &[], &Weights::default()).unwrap();
+        assert!(placement.block.positions().all(|pos| pos.x == 3));
+    }
+}
@@ -0,0 +1,208 @@
+use std::fmt;
+
+use bevy::math::IVec2;
+
+use super::{board::Board, movable_block::MovableBlock, move_gen::Collision};
+
+// occupancy only version of the board with one bitmask per row (bit n is column n),
+// for bots and simulations which copy and check boards far more often than the game
+// does. it isn't kept in sync with the entity board, take a snapshot with
+// `BitBoard::from(&board)` when one is needed.
+#[derive(Clone, PartialEq, Eq, Hash)]
+pub struct BitBoard {
+    width: usize,
+    height: usize,
+    rows: Vec<u32>,
+}
+impl BitBoard {
+    pub fn new(width: usize, height: usize) -> BitBoard {
+        assert!(width <= 32, "rows are stored as u32s");
+        BitBoard {
+            width,
+            height,
+            rows: vec![0; height],
+        }
+    }
+
+    pub fn width(&self) -> usize {
+        self.width
+    }
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    // rows from the bottom of the board up
+    pub fn rows(&self) -> &[u32] {
+        &self.rows
+    }
+
+    fn full_row(&self) -> u32 {
+        u32::MAX >> (32 - self.width)
+    }
+
+    pub fn spawn_position(&self) -> IVec2 {
+        IVec2::new((self.width / 2) as i32, self.height as i32 - 3)
+    }
+
+    pub fn is_occupied(&self, loc: IVec2) -> bool {
+        if loc.x < 0 || loc.y < 0 || loc.x >= (self.width as i32) || loc.y >= (self.height as i32) {
+            return true;
+        }
+        self.rows[loc.y as usize] & (1 << loc.x) != 0
+    }
+
+    pub fn set_occupied(&mut self, loc: IVec2) {
+        self.rows[loc.y as usize] |= 1 << loc.x;
+    }
+
+    pub fn can_place(&self, block: &MovableBlock) -> bool {
+        block.positions().all(|loc| !self.is_occupied(loc))
+    }
+
+    pub fn place_block(&mut self, block: &MovableBlock) {
+        for loc in block.positions() {
+            debug_assert!(!self.is_occupied(loc));
+            self.set_occupied(loc);
+        }
+    }
+
+    // returns the number of lines cleared
+    pub fn clear_filled_lines(&mut self) -> usize {
+        let full_row = self.full_row();
+        let before = self.rows.len();
+        self.rows.retain(|&row| row != full_row);
+        let cleared = before - self.rows.len();
+        self.rows.resize(self.height, 0);
+        cleared
+    }
+
+    // places the block and clears any lines it filled, returning how many there were
+    pub fn lock(&mut self, block: &MovableBlock) -> usize {
+        self.place_block(block);
+        self.clear_filled_lines()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.rows.iter().all(|&row| row == 0)
+    }
+
+    pub fn column_heights(&self) -> Vec<i32> {
+        let mut heights = vec![0; self.width];
+        for (y, &row) in self.rows.iter().enumerate() {
+            for (x, height) in heights.iter_mut().enumerate() {
+                if row & (1 << x) != 0 {
+                    *height = y as i32 + 1;
+                }
+            }
+        }
+        heights
+    }
+
+    // empty cells with a filled cell somewhere above them
+    pub fn holes(&self) -> u32 {
+        let mut covered = 0;
+        let mut holes = 0;
+        for &row in self.rows.iter().rev() {
+            holes += (covered & !row).count_ones();
+            covered |= row;
+        }
+        holes
+    }
+}
+
+impl From<&Board> for BitBoard {
+    fn from(board: &Board) -> Self {
+        let mut bits = BitBoard::new(board.width(), board.height());
+        for (pos, _) in board.iter_ents() {
+            bits.set_occupied(pos);
+        }
+        bits
+    }
+}
+
+impl Collision for BitBoard {
+    fn can_place(&self, block: &MovableBlock) -> bool {
+        BitBoard::can_place(self, block)
+    }
+    fn is_occupied(&self, loc: IVec2) -> bool {
+        BitBoard::is_occupied(self, loc)
+    }
+}
+
+impl fmt::Debug for BitBoard {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for &row in self.rows.iter().rev() {
+            let r = (0..self.width)
+                .map(|x| if row & (1 << x) != 0 { "██" } else { ".." })
+                .collect::<String>();
+            f.write_str(r.as_str())?;
+            f.write_str("\n")?
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use bevy::{ecs::entity::Entity, math::IVec2};
+
+    use crate::tetris_block::{
+        board::{Board, Cell},
+        movable_block::{BlockName, RotDir},
+    };
+
+    use super::BitBoard;
+
+    #[test]
+    fn test_matches_board() {
+        let mut board = Board::new(4, 4);
+        *board.cell_mut((1, 2).into()) = Some(Cell::garbage(Entity::from_raw(0)));
+        let bits = BitBoard::from(&board);
+        assert_eq!(bits.rows(), &[0, 0, 0b0010, 0]);
+
+        let block = BlockName::Test.create_movable((0, 0).into());
+        for x in -1..5 {
+            for y in -1..5 {
+                let moved = block.move_relative((x, y).into());
+                assert_eq!(bits.can_place(&moved), board.can_place(&moved));
+            }
+        }
+    }
+
+    #[test]
+    fn test_clear_and_holes() {
+        // ..██....
+        // ██████..
+        // ████████
+        let mut bits = BitBoard::new(4, 4);
+        for pos in [
+            (0, 0),
+            (1, 0),
+            (2, 0),
+            (3, 0),
+            (0, 1),
+            (1, 1),
+            (2, 1),
+            (1, 2),
+        ] {
+            bits.set_occupied(IVec2::from(pos));
+        }
+        assert_eq!(bits.holes(), 0);
+        assert_eq!(bits.column_heights(), vec![2, 3, 2, 1]);
+
+        assert_eq!(bits.clear_filled_lines(), 1);
+        assert_eq!(bits.rows(), &[0b0111, 0b0010, 0, 0]);
+
+        // a vertical I block down the empty right column clears the bottom row
+        let i = BlockName::I
+            .create_movable((3, 2).into())
+            .rotate(RotDir::Right)
+            .0;
+        assert_eq!(bits.lock(&i), 1);
+        assert_eq!(bits.rows(), &[0b1010, 0b1000, 0b1000, 0]);
+        assert_eq!(bits.holes(), 0);
+
+        bits.set_occupied((0, 3).into());
+        assert_eq!(bits.holes(), 3);
+    }
+}
@@ -4,16 +4,56 @@ use std::{
 };
 
 use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
 
-use super::movable_block::MovableBlock;
+use super::{
+    movable_block::{BlockName, MovableBlock},
+    move_gen::Collision,
+};
 
-type BoardCell = Option<Entity>;
-// #[derive(Clone, Copy, Eq, PartialEq)]
-// enum BoardCell {
-//     Empty,
-//     Placed(Entity),
-// }
+// what fills a cell: part of a placed block, or garbage
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Fill {
+    Piece(BlockName),
+    Garbage,
+}
+impl Fill {
+    // the usual colour for each piece
+    pub fn color(&self) -> Color {
+        match self {
+            Fill::Piece(BlockName::I) => Color::CYAN,
+            Fill::Piece(BlockName::O) => Color::YELLOW,
+            Fill::Piece(BlockName::T) => Color::PURPLE,
+            Fill::Piece(BlockName::S) => Color::GREEN,
+            Fill::Piece(BlockName::Z) => Color::RED,
+            Fill::Piece(BlockName::J) => Color::BLUE,
+            Fill::Piece(BlockName::L) => Color::ORANGE,
+            Fill::Piece(BlockName::Test) | Fill::Garbage => Color::GRAY,
+        }
+    }
+}
 
+// a filled cell, with the entity drawing it and what it was filled with, so the
+// board can be read without going through the ecs
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Cell {
+    pub ent: Entity,
+    pub fill: Fill,
+    pub color: Color,
+}
+impl Cell {
+    pub fn garbage(ent: Entity) -> Cell {
+        Cell {
+            ent,
+            fill: Fill::Garbage,
+            color: Fill::Garbage.color(),
+        }
+    }
+}
+
+type BoardCell = Option<Cell>;
+
+#[derive(Clone, Component)]
 pub struct Board {
     width: usize,
     height: usize,
@@ -39,6 +79,11 @@ impl Board {
         self.height
     }
 
+    // where new blocks appear, near the top middle of the board
+    pub fn spawn_position(&self) -> IVec2 {
+        IVec2::new((self.width / 2) as i32, self.height as i32 - 3)
+    }
+
     fn to_idx(&self, vec: IVec2) -> usize {
         ((self.width as i32 * vec.y) + vec.x) as usize
     }
@@ -54,27 +99,38 @@ impl Board {
         &mut self.cells[idx]
     }
 
-    pub fn iter_ents(&self) -> impl Iterator<Item = (IVec2, Entity)> + '_ {
+    pub fn iter_cells(&self) -> impl Iterator<Item = (IVec2, Cell)> + '_ {
         self.cells
             .iter()
             .enumerate()
-            .filter_map(|(idx, &ent)| ent.map(|ent| (self.to_ivec(idx), ent)))
+            .filter_map(|(idx, &cell)| cell.map(|cell| (self.to_ivec(idx), cell)))
+    }
+
+    pub fn iter_ents(&self) -> impl Iterator<Item = (IVec2, Entity)> + '_ {
+        self.iter_cells().map(|(pos, cell)| (pos, cell.ent))
     }
 
     pub fn can_place(&self, block: &MovableBlock) -> bool {
         block.positions().all(|loc| !self.is_occupied(loc))
     }
 
-    pub fn place_block(&mut self, block: &MovableBlock, ents: &[Entity]) {
+    pub fn place_block(&mut self, block: &MovableBlock, ents: &[Entity], color: Color) {
         assert!(block.positions().len() == ents.len());
         for (idx, loc) in block.positions().enumerate() {
-            self.set_occupied(loc, ents[idx]);
+            self.set_occupied(
+                loc,
+                Cell {
+                    ent: ents[idx],
+                    fill: Fill::Piece(block.name()),
+                    color,
+                },
+            );
         }
     }
 
-    fn set_occupied(&mut self, loc: IVec2, entity: Entity) {
+    fn set_occupied(&mut self, loc: IVec2, cell: Cell) {
         assert!(self.cell(loc).is_none());
-        *self.cell_mut(loc) = Some(entity);
+        *self.cell_mut(loc) = Some(cell);
     }
 
     pub fn clear_filled_lines(&mut self) -> (HashSet<Entity>, HashMap<Entity, IVec2>) {
@@ -84,32 +140,98 @@ impl Board {
         // from the top of the board, to the bottom, check full lines
         for row in (0..self.height).rev() {
             if self.is_row_full(row as usize) {
-                // remove all the entities in this row
-                for col in 0..self.width() {
-                    let pos = IVec2::new(col as i32, row as i32);
-                    if let Some(ent) = self.cell_mut(pos).take() {
-                        cleared_entities.insert(ent);
-                    }
+                self.remove_row(row, &mut cleared_entities, &mut moved_entities);
+            }
+        }
+
+        (cleared_entities, moved_entities)
+    }
+
+    // takes away the bottom rows whether they're full or not, dropping everything
+    // above down into their place
+    pub fn clear_bottom_rows(&mut self, rows: usize) -> (HashSet<Entity>, HashMap<Entity, IVec2>) {
+        let mut cleared_entities = HashSet::new();
+        let mut moved_entities = HashMap::new();
+        for _ in 0..rows.min(self.height) {
+            self.remove_row(0, &mut cleared_entities, &mut moved_entities);
+        }
+        (cleared_entities, moved_entities)
+    }
+
+    fn remove_row(
+        &mut self,
+        row: usize,
+        cleared_entities: &mut HashSet<Entity>,
+        moved_entities: &mut HashMap<Entity, IVec2>,
+    ) {
+        // remove all the entities in this row
+        for col in 0..self.width() {
+            let pos = IVec2::new(col as i32, row as i32);
+            if let Some(cell) = self.cell_mut(pos).take() {
+                moved_entities.remove(&cell.ent);
+                cleared_entities.insert(cell.ent);
+            }
+        }
+
+        for row_ in row..(self.height() - 1) {
+            // move everything from the rows above down one 'y' position
+            for col in 0..self.width() {
+                let from = IVec2::new(col as i32, (row_ + 1) as i32);
+                let to = IVec2::new(col as i32, row_ as i32);
+
+                let cell = self.cell(from);
+                if let Some(cell) = cell {
+                    moved_entities.insert(cell.ent, to);
                 }
+                *self.cell_mut(to) = cell;
+                *self.cell_mut(from) = None;
+            }
+        }
+    }
+
+    // pushes everything up to make room for a garbage row at the bottom for each hole
+    // column (holes[0] is the bottom row), filling them with garbage cells from `spawn_cell`.
+    // returns the entities pushed off the top of the board, and where the rest went.
+    pub fn insert_garbage_rows(
+        &mut self,
+        holes: &[usize],
+        mut spawn_cell: impl FnMut(IVec2) -> Entity,
+    ) -> (HashSet<Entity>, HashMap<Entity, IVec2>) {
+        let mut pushed_out = HashSet::new();
+        let mut moved_entities = HashMap::new();
+        let num_rows = holes.len().min(self.height);
+
+        // from the top of the board down, so nothing is overwritten before it's moved
+        for row in (0..self.height).rev() {
+            for col in 0..self.width() {
+                let from = IVec2::new(col as i32, row as i32);
+                let cell = match self.cell_mut(from).take() {
+                    Some(cell) => cell,
+                    None => continue,
+                };
 
-                for row_ in row..(self.height() - 1) {
-                    // move everything from the rows above down one 'y' position
-                    for col in 0..self.width() {
-                        let from = IVec2::new(col as i32, (row_ + 1) as i32);
-                        let to = IVec2::new(col as i32, row_ as i32);
-
-                        let cell = self.cell(from);
-                        if let Some(ent) = cell {
-                            moved_entities.insert(ent, to);
-                        }
-                        *self.cell_mut(to) = cell;
-                        *self.cell_mut(from) = None;
-                    }
+                let to = from + IVec2::new(0, num_rows as i32);
+                if to.y >= self.height as i32 {
+                    pushed_out.insert(cell.ent);
+                } else {
+                    moved_entities.insert(cell.ent, to);
+                    *self.cell_mut(to) = Some(cell);
                 }
             }
         }
 
-        (cleared_entities, moved_entities)
+        for (row, &hole) in holes.iter().take(num_rows).enumerate() {
+            for col in (0..self.width()).filter(|&col| col != hole) {
+                let pos = IVec2::new(col as i32, row as i32);
+                self.set_occupied(pos, Cell::garbage(spawn_cell(pos)));
+            }
+        }
+
+        (pushed_out, moved_entities)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.cells.iter().all(|cell| cell.is_none())
     }
 
     fn is_occupied(&self, loc: IVec2) -> bool {
@@ -140,6 +262,15 @@ impl Board {
     }
 }
 
+impl Collision for Board {
+    fn can_place(&self, block: &MovableBlock) -> bool {
+        Board::can_place(self, block)
+    }
+    fn is_occupied(&self, loc: IVec2) -> bool {
+        Board::is_occupied(self, loc)
+    }
+}
+
 impl fmt::Debug for Board {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         f.write_fmt(format_args!("Board State({})\n", self.rows().len()))?;
@@ -165,9 +296,11 @@ impl fmt::Debug for Board {
 
 #[cfg(test)]
 mod test {
+    use bevy::{ecs::entity::Entity, math::IVec2, render::color::Color};
+
     use crate::tetris_block::movable_block::BlockName;
 
-    use super::Board;
+    use super::{Board, Cell, Fill};
 
     #[test]
     fn test() {
@@ -181,4 +314,61 @@ mod test {
         assert!(!board.can_place(&block.move_relative((-1, 0).into())));
         assert!(!board.can_place(&block.move_relative((3, 0).into())));
     }
+
+    #[test]
+    fn test_insert_garbage_rows() {
+        let mut board = Board::new(3, 3);
+        let bottom = Entity::from_raw(100);
+        let top = Entity::from_raw(101);
+        *board.cell_mut((1, 0).into()) = Some(Cell::garbage(bottom));
+        *board.cell_mut((2, 2).into()) = Some(Cell::garbage(top));
+
+        let mut next_id = 0;
+        let (pushed_out, moved) = board.insert_garbage_rows(&[0], |_| {
+            next_id += 1;
+            Entity::from_raw(next_id)
+        });
+        assert_eq!(next_id, 2);
+        assert!(pushed_out.contains(&top));
+        assert_eq!(moved.get(&bottom), Some(&IVec2::new(1, 1)));
+        assert_eq!(board.cell((0, 0).into()), None);
+        assert!(board.cell((1, 0).into()).is_some());
+        assert!(board.cell((2, 0).into()).is_some());
+        assert_eq!(board.cell((2, 2).into()), None);
+    }
+
+    #[test]
+    fn test_clear_bottom_rows() {
+        let mut board = Board::new(3, 3);
+        let bottom = Entity::from_raw(0);
+        let middle = Entity::from_raw(1);
+        let top = Entity::from_raw(2);
+        *board.cell_mut((0, 0).into()) = Some(Cell::garbage(bottom));
+        *board.cell_mut((1, 1).into()) = Some(Cell::garbage(middle));
+        *board.cell_mut((2, 2).into()) = Some(Cell::garbage(top));
+
+        let (cleared, moved) = board.clear_bottom_rows(2);
+        assert_eq!(cleared.len(), 2);
+        assert!(cleared.contains(&bottom) && cleared.contains(&middle));
+        assert!(!moved.contains_key(&middle));
+        assert_eq!(moved.get(&top), Some(&IVec2::new(2, 0)));
+        assert_eq!(board.cell((2, 0).into()), Some(Cell::garbage(top)));
+        assert_eq!(board.iter_ents().count(), 1);
+    }
+
+    #[test]
+    fn test_place_block() {
+        let mut board = Board::new(4, 4);
+        let block = BlockName::T.create_movable((1, 1).into());
+        let ents: Vec<_> = (0..4).map(Entity::from_raw).collect();
+        board.place_block(&block, &ents, Color::PINK);
+
+        let cells: Vec<_> = board.iter_cells().collect();
+        assert_eq!(cells.len(), 4);
+        for (pos, cell) in cells {
+            assert!(block.positions().any(|block_pos| block_pos == pos));
+            assert_eq!(cell.fill, Fill::Piece(BlockName::T));
+            assert_eq!(cell.color, Color::PINK);
+        }
+    }
 }
@@ -3,9 +3,13 @@ use std::f32::consts::TAU;
 use bevy::prelude::*;
 use lazy_static::lazy_static;
 
-use crate::{CELL_SIDE_LEN, GRID_CELLS};
+use crate::CELL_SIDE_LEN;
 
-use super::block_definition::BlockDefinition;
+use super::{
+    block_definition::BlockDefinition,
+    settings::Settings,
+    tweening_position::{tick_cell_position_times, Animation, TweeningTransform},
+};
 
 #[derive(Component)]
 pub struct AbsolutePositionedPiece {
@@ -30,25 +34,81 @@ pub struct AbsolutePositionedCell {
 pub struct CellPositioningPlugin;
 impl Plugin for CellPositioningPlugin {
     fn build(&self, app: &mut App) {
-        // xxx - wrap all this in a system set so it can be made to run after new block positions are calculated
-        app.add_system(set_relative_positioned_cell)
-            .add_system(set_absolute_positioned_cell)
-            .add_system(set_absolute_positioned_piece);
+        // after the new block positions are calculated, and before the tweens move
+        // things along
+        app.add_system_to_stage(
+            "update_block_transforms",
+            set_relative_positioned_cell.before(tick_cell_position_times),
+        )
+        .add_system_to_stage(
+            "update_block_transforms",
+            set_absolute_positioned_cell.before(tick_cell_position_times),
+        )
+        .add_system_to_stage(
+            "update_block_transforms",
+            set_absolute_positioned_piece.before(tick_cell_position_times),
+        );
     }
 }
 
+// from the middle of the board to its bottom left corner
+fn shift_to_corner(settings: &Settings) -> Vec3 {
+    -Vec3::new(
+        settings.board_width as f32,
+        settings.board_height as f32,
+        0.,
+    ) * CELL_SIDE_LEN
+        / 2.
+}
+
 lazy_static! {
-    static ref SCREEN_DIMS: Vec3 =
-        Vec3::new(GRID_CELLS.width as f32, GRID_CELLS.height as f32, 0.) * CELL_SIDE_LEN;
-    static ref SHIFT_TO_CORNER: Vec3 = -*SCREEN_DIMS / 2.;
     // static ref HALF_CELL: Vec3 = Vec3::new(CELL_SIDE_LEN / 2., CELL_SIDE_LEN / 2., 0.);
     static ref HALF_CELL: Vec3 = Vec3::new(CELL_SIDE_LEN / 2., CELL_SIDE_LEN / 2., 0.);
 }
 
+// moves the transform to the target, or starts it on its way there if animated
+fn move_to(
+    commands: &mut Commands,
+    animation: &Animation,
+    ent: Entity,
+    t: &mut Transform,
+    tween: Option<Mut<TweeningTransform>>,
+    start: Transform,
+    target: Transform,
+) {
+    match (animation.easing, tween) {
+        (Some(_), Some(mut tween)) => tween.set_target(&target),
+        (Some(easing), None) => {
+            let mut tween = TweeningTransform::new(&start, animation.duration, easing);
+            tween.set_target(&target);
+            *t = tween.transform();
+            commands.entity(ent).insert(tween);
+        }
+        (None, tween) => {
+            *t = target;
+            if tween.is_some() {
+                commands.entity(ent).remove::<TweeningTransform>();
+            }
+        }
+    }
+}
+
+#[allow(clippy::type_complexity)]
 fn set_absolute_positioned_piece(
-    mut query: Query<(&mut Transform, &AbsolutePositionedPiece), Changed<AbsolutePositionedPiece>>,
+    mut commands: Commands,
+    animation: Res<Animation>,
+    settings: Res<Settings>,
+    mut query: Query<
+        (
+            Entity,
+            &mut Transform,
+            Option<&mut TweeningTransform>,
+            &AbsolutePositionedPiece,
+        ),
+        Changed<AbsolutePositionedPiece>,
+    >,
 ) {
-    for (mut t, p) in query.iter_mut() {
+    for (ent, mut t, tween, p) in query.iter_mut() {
         let maybe_half_cell = if p.def.around_corner {
             *HALF_CELL
         } else {
@@ -76,13 +136,24 @@ fn set_absolute_positioned_piece(
         // then apply an offset to shift it to the right cell location, and add half a cell
         // of offset, undoing the half-shift from the corner if needed
         mat = Transform {
-            translation: *SHIFT_TO_CORNER + *HALF_CELL + corner_to_position - maybe_half_cell,
+            translation: shift_to_corner(&settings) + *HALF_CELL + corner_to_position
+                - maybe_half_cell,
             ..default()
         }
         .compute_matrix()
         .mul_mat4(&mat);
 
-        *t = Transform::from_matrix(mat);
+        // a new block appears where it spawned, rather than sliding in
+        let target = Transform::from_matrix(mat);
+        move_to(
+            &mut commands,
+            &animation,
+            ent,
+            &mut t,
+            tween,
+            target,
+            target,
+        );
     }
 }
 
@@ -96,18 +167,47 @@ fn set_relative_positioned_cell(
         };
     }
 }
+#[allow(clippy::type_complexity)]
 fn set_absolute_positioned_cell(
-    mut query: Query<(&mut Transform, &AbsolutePositionedCell), Changed<AbsolutePositionedCell>>,
+    mut commands: Commands,
+    animation: Res<Animation>,
+    settings: Res<Settings>,
+    mut query: Query<
+        (
+            Entity,
+            &mut Transform,
+            Option<&mut TweeningTransform>,
+            &AbsolutePositionedCell,
+            &GlobalTransform,
+            Option<&Parent>,
+            Option<&RelativePositionedCell>,
+        ),
+        Changed<AbsolutePositionedCell>,
+    >,
+    parents: Query<&GlobalTransform>,
 ) {
-    for (mut t, p) in query.iter_mut() {
+    for (ent, mut t, tween, p, global, parent, relative) in query.iter_mut() {
         println!("setting abs position to {}", p.pos);
         let corner_to_position = Vec3::new(p.pos.x as f32, p.pos.y as f32, 0.) * CELL_SIDE_LEN;
-        let translation = *SHIFT_TO_CORNER + corner_to_position + *HALF_CELL;
+        let translation = shift_to_corner(&settings) + corner_to_position + *HALF_CELL;
         let rotation = Quat::from_rotation_z(-TAU * (p.rot as f32 / 4.));
-        *t = Transform {
+        let target = Transform {
             translation,
             rotation,
             ..default()
         };
+
+        // a cell that was part of a block carries on from where the block was last
+        // drawn (which makes hard drops slide down), new cells just appear
+        let parent_global = parent.and_then(|parent| parents.get(parent.0).ok());
+        let start = match (relative, parent_global) {
+            (Some(_), Some(parent_global)) if *global != GlobalTransform::identity() => {
+                Transform::from_matrix(
+                    parent_global.compute_matrix().inverse() * global.compute_matrix(),
+                )
+            }
+            _ => target,
+        };
+        move_to(&mut commands, &animation, ent, &mut t, tween, start, target);
     }
 }
@@ -0,0 +1,440 @@
+// what the keys and gamepad buttons do. each player plays with a layout of bindings,
+// which are kept in the settings and can be changed from the menu. every frame,
+// what's been pressed is turned into the actions for each player, before anything
+// else looks at the input.
+
+use std::{
+    collections::{BTreeMap, HashSet},
+    fmt,
+    time::Duration,
+};
+
+use bevy::{input::InputSystem, prelude::*};
+use serde::{Deserialize, Serialize};
+
+use super::settings::Settings;
+
+// how far a stick has to be pushed to count as a press
+const STICK_THRESHOLD: f32 = 0.5;
+// held down, these repeat after the das
+const REPEATING: &[InputAction] = &[
+    InputAction::MoveLeft,
+    InputAction::MoveRight,
+    InputAction::SoftDrop,
+];
+// with an arr of 0, enough repeats a frame to cross any board
+const INSTANT_REPEATS: usize = 64;
+const STICK_AXES: &[GamepadAxisType] = &[
+    GamepadAxisType::LeftStickX,
+    GamepadAxisType::LeftStickY,
+    GamepadAxisType::RightStickX,
+    GamepadAxisType::RightStickY,
+];
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum InputAction {
+    MoveLeft,
+    MoveRight,
+    SoftDrop,
+    HardDrop,
+    RotateLeft,
+    RotateRight,
+    Hold,
+    Pause,
+    // (for debugging) stops and starts every board's gravity
+    ToggleGravity,
+}
+impl InputAction {
+    pub const ALL: &'static [InputAction] = &[
+        InputAction::MoveLeft,
+        InputAction::MoveRight,
+        InputAction::SoftDrop,
+        InputAction::HardDrop,
+        InputAction::RotateLeft,
+        InputAction::RotateRight,
+        InputAction::Hold,
+        InputAction::Pause,
+        InputAction::ToggleGravity,
+    ];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            InputAction::MoveLeft => "MOVE LEFT",
+            InputAction::MoveRight => "MOVE RIGHT",
+            InputAction::SoftDrop => "SOFT DROP",
+            InputAction::HardDrop => "HARD DROP",
+            InputAction::RotateLeft => "ROTATE LEFT",
+            InputAction::RotateRight => "ROTATE RIGHT",
+            InputAction::Hold => "HOLD",
+            InputAction::Pause => "PAUSE",
+            InputAction::ToggleGravity => "GRAVITY",
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Binding {
+    Key(KeyCode),
+    // on the player's gamepad
+    Button(GamepadButtonType),
+    // pushing a stick one way, `positive` being right or up
+    Stick {
+        axis: GamepadAxisType,
+        positive: bool,
+    },
+}
+impl fmt::Display for Binding {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Binding::Key(key) => write!(f, "{:?}", key),
+            Binding::Button(button) => write!(f, "{:?}", button),
+            Binding::Stick { axis, positive } => {
+                write!(f, "{:?}{}", axis, if *positive { "+" } else { "-" })
+            }
+        }
+    }
+}
+
+// which set of bindings a player uses. a single player gets the whole keyboard,
+// split screen players half each.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Layout {
+    Single,
+    LeftSide,
+    RightSide,
+}
+impl Layout {
+    pub const ALL: &'static [Layout] = &[Layout::Single, Layout::LeftSide, Layout::RightSide];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Layout::Single => "single",
+            Layout::LeftSide => "left side",
+            Layout::RightSide => "right side",
+        }
+    }
+}
+
+// any number of keys, buttons and sticks can do the same thing
+pub type Bindings = BTreeMap<InputAction, Vec<Binding>>;
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ControlsConfig {
+    pub single: Bindings,
+    pub left_side: Bindings,
+    pub right_side: Bindings,
+}
+impl Default for ControlsConfig {
+    fn default() -> Self {
+        use InputAction::*;
+        let keys = |keys: [KeyCode; 7]| {
+            let actions = [
+                MoveLeft,
+                MoveRight,
+                SoftDrop,
+                HardDrop,
+                RotateLeft,
+                RotateRight,
+                Hold,
+            ];
+            let mut bindings: Bindings = actions
+                .into_iter()
+                .zip(keys)
+                .map(|(action, key)| (action, vec![Binding::Key(key)]))
+                .collect();
+            bindings.insert(Pause, vec![Binding::Key(KeyCode::Escape)]);
+            bindings.insert(ToggleGravity, vec![Binding::Key(KeyCode::Space)]);
+            // every layout has the same gamepad bindings, each player has their own pad
+            for (action, button) in [
+                (MoveLeft, GamepadButtonType::DPadLeft),
+                (MoveRight, GamepadButtonType::DPadRight),
+                (SoftDrop, GamepadButtonType::DPadDown),
+                (HardDrop, GamepadButtonType::DPadUp),
+                (RotateLeft, GamepadButtonType::West),
+                (RotateRight, GamepadButtonType::South),
+                (Hold, GamepadButtonType::LeftTrigger),
+                (Hold, GamepadButtonType::RightTrigger),
+                (Pause, GamepadButtonType::Start),
+            ] {
+                bindings
+                    .entry(action)
+                    .or_default()
+                    .push(Binding::Button(button));
+            }
+            for (action, positive) in [(MoveLeft, false), (MoveRight, true)] {
+                bindings.entry(action).or_default().push(Binding::Stick {
+                    axis: GamepadAxisType::LeftStickX,
+                    positive,
+                });
+            }
+            bindings.entry(SoftDrop).or_default().push(Binding::Stick {
+                axis: GamepadAxisType::LeftStickY,
+                positive: false,
+            });
+            bindings
+        };
+        ControlsConfig {
+            single: keys([
+                KeyCode::Left,
+                KeyCode::Right,
+                KeyCode::Down,
+                KeyCode::Up,
+                KeyCode::A,
+                KeyCode::D,
+                KeyCode::LShift,
+            ]),
+            left_side: keys([
+                KeyCode::A,
+                KeyCode::D,
+                KeyCode::S,
+                KeyCode::W,
+                KeyCode::Q,
+                KeyCode::E,
+                KeyCode::LShift,
+            ]),
+            right_side: keys([
+                KeyCode::Left,
+                KeyCode::Right,
+                KeyCode::Down,
+                KeyCode::Up,
+                KeyCode::Comma,
+                KeyCode::Period,
+                KeyCode::RShift,
+            ]),
+        }
+    }
+}
+impl ControlsConfig {
+    pub fn layout(&self, layout: Layout) -> &Bindings {
+        match layout {
+            Layout::Single => &self.single,
+            Layout::LeftSide => &self.left_side,
+            Layout::RightSide => &self.right_side,
+        }
+    }
+
+    pub fn layout_mut(&mut self, layout: Layout) -> &mut Bindings {
+        match layout {
+            Layout::Single => &mut self.single,
+            Layout::LeftSide => &mut self.left_side,
+            Layout::RightSide => &mut self.right_side,
+        }
+    }
+}
+
+// how a player's block is moved
+#[derive(Component, Clone)]
+pub(super) struct Controls {
+    pub layout: Layout,
+    // which of the connected gamepads is theirs, in the order they were connected
+    pub gamepad: usize,
+}
+
+// the actions a player's bindings were pressed for this frame
+#[derive(Component, Default)]
+pub(super) struct PressedActions(pub Vec<InputAction>);
+impl PressedActions {
+    pub fn contains(&self, action: InputAction) -> bool {
+        self.0.contains(&action)
+    }
+}
+
+// how long each repeating action has been held for
+#[derive(Component, Default)]
+pub(super) struct AutoRepeat(BTreeMap<InputAction, Duration>);
+
+// takes an action out of every player's presses, so that whatever acts on it is the
+// only thing that does. true if anyone pressed it.
+pub(super) fn take_action(players: &mut Query<&mut PressedActions>, action: InputAction) -> bool {
+    let mut pressed = false;
+    for mut actions in players.iter_mut() {
+        if actions.contains(action) {
+            actions.0.retain(|&other| other != action);
+            pressed = true;
+        }
+    }
+    pressed
+}
+
+pub struct ControlsPlugin;
+impl Plugin for ControlsPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_system_to_stage(CoreStage::PreUpdate, read_bindings.after(InputSystem));
+    }
+}
+
+type StickPush = (Gamepad, GamepadAxisType, bool);
+
+// every stick pushed far enough one way or the other
+fn pushed_sticks(gamepads: &Gamepads, axes: &Axis<GamepadAxis>) -> HashSet<StickPush> {
+    let mut pushed = HashSet::new();
+    for &gamepad in gamepads.iter() {
+        for &axis in STICK_AXES {
+            let value = axes.get(GamepadAxis(gamepad, axis)).unwrap_or(0.);
+            if value.abs() > STICK_THRESHOLD {
+                pushed.insert((gamepad, axis, value > 0.));
+            }
+        }
+    }
+    pushed
+}
+
+// the gamepads in the order they were connected, which is the order of their ids
+fn sorted_gamepads(gamepads: &Gamepads) -> Vec<Gamepad> {
+    let mut sorted: Vec<Gamepad> = gamepads.iter().copied().collect();
+    sorted.sort_by_key(|gamepad| gamepad.0);
+    sorted
+}
+
+// how many times an action repeats while it goes from being held for `before` to
+// being held for `after`
+fn repeats(before: Duration, after: Duration, das: Duration, arr: Duration) -> usize {
+    if after < das {
+        0
+    } else if arr.is_zero() {
+        INSTANT_REPEATS
+    } else {
+        let count = |held: Duration| {
+            if held < das {
+                0
+            } else {
+                ((held - das).as_nanos() / arr.as_nanos()) as usize + 1
+            }
+        };
+        count(after) - count(before)
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+fn read_bindings(
+    time: Res<Time>,
+    settings: Res<Settings>,
+    kb: Res<Input<KeyCode>>,
+    buttons: Res<Input<GamepadButton>>,
+    axes: Res<Axis<GamepadAxis>>,
+    gamepads: Res<Gamepads>,
+    // the sticks that were already pushed last frame, so holding one isn't a press
+    // every frame
+    mut held_sticks: Local<HashSet<StickPush>>,
+    mut players: Query<(&Controls, &mut PressedActions, &mut AutoRepeat)>,
+) {
+    let das = Duration::from_millis(settings.das_ms);
+    let arr = Duration::from_millis(settings.arr_ms);
+    let pushed = pushed_sticks(&gamepads, &axes);
+    let sorted = sorted_gamepads(&gamepads);
+    for (controls, mut pressed, mut repeat) in players.iter_mut() {
+        let gamepad = sorted.get(controls.gamepad).copied();
+        let just_pressed = |binding: &Binding| match *binding {
+            Binding::Key(key) => kb.just_pressed(key),
+            Binding::Button(button) => {
+                gamepad.is_some_and(|gamepad| buttons.just_pressed(GamepadButton(gamepad, button)))
+            }
+            Binding::Stick { axis, positive } => gamepad.is_some_and(|gamepad| {
+                let push = (gamepad, axis, positive);
+                pushed.contains(&push) && !held_sticks.contains(&push)
+            }),
+        };
+        let held = |binding: &Binding| match *binding {
+            Binding::Key(key) => kb.pressed(key),
+            Binding::Button(button) => {
+                gamepad.is_some_and(|gamepad| buttons.pressed(GamepadButton(gamepad, button)))
+            }
+            Binding::Stick { axis, positive } => {
+                gamepad.is_some_and(|gamepad| pushed.contains(&(gamepad, axis, positive)))
+            }
+        };
+
+        let mut actions = Vec::new();
+        for (&action, bindings) in settings.controls.layout(controls.layout) {
+            if bindings.iter().any(just_pressed) {
+                actions.push(action);
+                if REPEATING.contains(&action) {
+                    repeat.0.insert(action, Duration::ZERO);
+                }
+            } else if let Some(before) = repeat.0.get(&action).copied() {
+                if bindings.iter().any(held) {
+                    let after = before + time.delta();
+                    let count = repeats(before, after, das, arr);
+                    actions.extend(std::iter::repeat_n(action, count));
+                    repeat.0.insert(action, after);
+                } else {
+                    repeat.0.remove(&action);
+                }
+            }
+        }
+        pressed.0 = actions;
+    }
+    *held_sticks = pushed;
+}
+
+// the first key, button or stick pressed this frame, for rebinding an action
+pub(super) fn any_pressed(
+    kb: &Input<KeyCode>,
+    buttons: &Input<GamepadButton>,
+    axes: &Axis<GamepadAxis>,
+    gamepads: &Gamepads,
+) -> Option<Binding> {
+    if let Some(&key) = kb.get_just_pressed().next() {
+        return Some(Binding::Key(key));
+    }
+    if let Some(&GamepadButton(_, button)) = buttons.get_just_pressed().next() {
+        return Some(Binding::Button(button));
+    }
+    pushed_sticks(gamepads, axes)
+        .into_iter()
+        .next()
+        .map(|(_, axis, positive)| Binding::Stick { axis, positive })
+}
+
+#[cfg(test)]
+mod test {
+    use bevy::input::{gamepad::GamepadAxisType, keyboard::KeyCode};
+
+    use std::time::Duration;
+
+    use super::{repeats, Binding, ControlsConfig, InputAction, Layout, INSTANT_REPEATS};
+
+    #[test]
+    fn test_default_controls() {
+        let config = ControlsConfig::default();
+        for &layout in Layout::ALL {
+            let bindings = config.layout(layout);
+            for action in InputAction::ALL {
+                assert!(!bindings[action].is_empty(), "{:?} {:?}", layout, action);
+            }
+        }
+        assert!(config.left_side[&InputAction::MoveLeft].contains(&Binding::Key(KeyCode::A)));
+        assert!(
+            config.single[&InputAction::MoveLeft].contains(&Binding::Stick {
+                axis: GamepadAxisType::LeftStickX,
+                positive: false,
+            })
+        );
+    }
+
+    #[test]
+    fn test_repeats() {
+        let ms = Duration::from_millis;
+        let (das, arr) = (ms(100), ms(20));
+        assert_eq!(repeats(ms(0), ms(99), das, arr), 0);
+        assert_eq!(repeats(ms(99), ms(100), das, arr), 1);
+        assert_eq!(repeats(ms(100), ms(119), das, arr), 0);
+        assert_eq!(repeats(ms(110), ms(150), das, arr), 2);
+        assert_eq!(repeats(ms(100), ms(110), das, ms(0)), INSTANT_REPEATS);
+    }
+
+    #[test]
+    fn test_controls_json() {
+        let config = ControlsConfig::default();
+        let json = serde_json::to_string(&config).unwrap();
+        assert!(json.contains(r#""move_left":[{"key":"Left"}"#));
+        assert_eq!(
+            serde_json::from_str::<ControlsConfig>(&json).unwrap(),
+            config
+        );
+    }
+}
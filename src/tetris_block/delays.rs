@@ -0,0 +1,175 @@
+// the pauses between placing a block and the next one coming in. lines are taken off
+// the board as soon as they're cleared, but the cells stay on screen flashing or
+// dissolving for the line clear delay, then everything above drops down. the entry
+// delay (ARE) comes after that, or straight after placing if nothing was cleared.
+
+use std::time::Duration;
+
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use super::{cell_positioning::AbsolutePositionedCell, spawn_new_block, Step};
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ClearEffect {
+    // the cells go white and fade out
+    Flash,
+    // the cells shrink away in their own colour
+    Dissolve,
+}
+impl ClearEffect {
+    pub fn from_name(name: &str) -> Option<ClearEffect> {
+        match name {
+            "flash" => Some(ClearEffect::Flash),
+            "dissolve" => Some(ClearEffect::Dissolve),
+            _ => None,
+        }
+    }
+}
+
+// both ends of an online game need the same delays
+pub struct Delays {
+    pub line_clear: Duration,
+    pub entry: Duration,
+    pub clear_effect: ClearEffect,
+}
+impl Default for Delays {
+    fn default() -> Self {
+        Delays {
+            line_clear: Duration::from_millis(250),
+            entry: Duration::ZERO,
+            clear_effect: ClearEffect::Flash,
+        }
+    }
+}
+
+pub struct DelaysPlugin;
+impl Plugin for DelaysPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<Delays>()
+            .add_system_to_stage("spawn_new_blocks", tick_delays.before(spawn_new_block))
+            .add_system(animate_line_clears);
+    }
+}
+
+// cells from cleared lines, waiting to be despawned
+#[derive(Component)]
+pub struct LineClearDelay {
+    pub timer: Timer,
+    pub cleared: Vec<Entity>,
+    // where the cells above the cleared lines drop to
+    pub moved: Vec<(Entity, IVec2)>,
+}
+
+// counts down to the next block, once any cleared lines are gone
+#[derive(Component)]
+pub struct EntryDelay(pub Timer);
+
+// is the player between blocks? the timers are checked rather than whether the
+// components are there, so the next block comes in on the step the delay runs out
+pub(super) fn waiting(clearing: Option<&LineClearDelay>, entry: Option<&EntryDelay>) -> bool {
+    clearing.is_some_and(|clearing| !clearing.timer.finished())
+        || entry.is_some_and(|entry| !entry.0.finished())
+}
+
+fn tick_delays(
+    mut commands: Commands,
+    step: Res<Step>,
+    mut players: Query<(Entity, Option<&mut LineClearDelay>, Option<&mut EntryDelay>)>,
+    mut cell_query: Query<&mut AbsolutePositionedCell>,
+) {
+    for (player, clearing, entry) in players.iter_mut() {
+        if let Some(mut clearing) = clearing {
+            if !clearing.timer.tick(step.delta).finished() {
+                continue;
+            }
+            for ent in clearing.cleared.drain(..) {
+                commands.entity(ent).despawn_recursive();
+            }
+            for (ent, pos) in clearing.moved.drain(..) {
+                if let Ok(mut c) = cell_query.get_mut(ent) {
+                    c.pos = pos;
+                }
+            }
+            commands.entity(player).remove::<LineClearDelay>();
+        }
+
+        if let Some(mut entry) = entry {
+            if entry.0.tick(step.delta).finished() {
+                commands.entity(player).remove::<EntryDelay>();
+            }
+        }
+    }
+}
+
+// skins drawn from a strip use atlas sprites, the rest plain ones
+#[allow(clippy::type_complexity)]
+fn animate_line_clears(
+    delays: Res<Delays>,
+    players: Query<&LineClearDelay>,
+    cells: Query<&Children>,
+    mut sprites: Query<(&mut Sprite, &mut Transform), Without<TextureAtlasSprite>>,
+    mut atlas_sprites: Query<(&mut TextureAtlasSprite, &mut Transform), Without<Sprite>>,
+) {
+    for clearing in players.iter() {
+        let left = 1. - clearing.timer.percent();
+        for children in clearing
+            .cleared
+            .iter()
+            .filter_map(|&ent| cells.get(ent).ok())
+        {
+            for &child in children.iter() {
+                if let Ok((mut sprite, mut transform)) = sprites.get_mut(child) {
+                    fade(delays.clear_effect, left, &mut sprite.color, &mut transform);
+                } else if let Ok((mut sprite, mut transform)) = atlas_sprites.get_mut(child) {
+                    fade(delays.clear_effect, left, &mut sprite.color, &mut transform);
+                }
+            }
+        }
+    }
+}
+
+fn fade(effect: ClearEffect, left: f32, color: &mut Color, transform: &mut Transform) {
+    match effect {
+        ClearEffect::Flash => *color = Color::rgba(1., 1., 1., left),
+        ClearEffect::Dissolve => {
+            color.set_a(left);
+            transform.scale = Vec3::new(left, left, 1.);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::Duration;
+
+    use bevy::core::Timer;
+
+    use super::{waiting, EntryDelay, LineClearDelay};
+
+    #[test]
+    fn test_waiting() {
+        let clearing = |secs| LineClearDelay {
+            timer: Timer::from_seconds(secs, false),
+            cleared: Vec::new(),
+            moved: Vec::new(),
+        };
+        assert!(!waiting(None, None));
+        assert!(waiting(Some(&clearing(0.5)), None));
+        assert!(waiting(
+            None,
+            Some(&EntryDelay(Timer::from_seconds(0.1, false)))
+        ));
+
+        let mut done = clearing(0.5);
+        done.timer.tick(Duration::from_secs(1));
+        assert!(!waiting(Some(&done), None));
+
+        // nothing to wait for, but the timer still has to be ticked once
+        let mut zero = EntryDelay(Timer::from_seconds(0., false));
+        assert!(waiting(None, Some(&zero)));
+        zero.0.tick(Duration::ZERO);
+        assert!(!waiting(None, Some(&zero)));
+    }
+}
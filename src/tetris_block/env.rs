@@ -0,0 +1,331 @@
+// a headless version of the game for training agents: no rendering, no timers, the
+// game only moves forward when `step` is called
+//
+//     let mut env = Env::new(EnvConfig::default());
+//     let mut obs = env.reset(seed);
+//     loop {
+//         let (next_obs, reward, done) = env.step(Action::Place(pick(&obs)));
+//         ...
+//     }
+
+use bevy::math::IVec2;
+
+use crate::GRID_CELLS;
+
+use super::{
+    bit_board::BitBoard,
+    movable_block::{BlockName, MovableBlock},
+    move_gen::{apply_move, is_resting, reachable_placements, Move, Placement},
+    piece_queue::{PieceQueue, Randomizer},
+};
+
+pub const STANDARD_BLOCKS: &[BlockName] = &[
+    BlockName::L,
+    BlockName::J,
+    BlockName::O,
+    BlockName::I,
+    BlockName::T,
+    BlockName::S,
+    BlockName::Z,
+];
+
+// what picking a placement that isn't listed costs, so a training loop sees it
+pub const INVALID_PLACE_REWARD: f32 = -1.;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum ActionSpace {
+    // actions are single inputs, like a player pressing keys
+    Inputs,
+    // actions pick one of the placements listed in the observation
+    Placements,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Action {
+    Move(Move),
+    HardDrop,
+    Hold,
+    // index into `Observation::placements`. one that isn't listed ends the game, with
+    // `INVALID_PLACE_REWARD`
+    Place(usize),
+}
+
+#[derive(Clone, Debug)]
+pub struct EnvConfig {
+    pub width: usize,
+    pub height: usize,
+    pub pieces: &'static [BlockName],
+    pub action_space: ActionSpace,
+    // with raw inputs, the active block falls a cell every this many steps, and is
+    // locked if it can't. None turns gravity off.
+    pub gravity_steps: Option<u32>,
+}
+impl Default for EnvConfig {
+    fn default() -> Self {
+        EnvConfig {
+            width: GRID_CELLS.width as usize,
+            height: GRID_CELLS.height as usize,
+            pieces: STANDARD_BLOCKS,
+            action_space: ActionSpace::Placements,
+            gravity_steps: Some(20),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PieceState {
+    pub name: BlockName,
+    pub position: IVec2,
+    pub rotation: usize,
+    pub cells: Vec<IVec2>,
+}
+impl PieceState {
+    fn of(block: &MovableBlock) -> PieceState {
+        PieceState {
+            name: block.name(),
+            position: block.root_position(),
+            rotation: block.rotation_state(),
+            cells: block.positions().collect(),
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Observation {
+    pub width: usize,
+    pub height: usize,
+    // row by row from the bottom of the board, true where a cell is filled
+    pub grid: Vec<bool>,
+    // None once the game is over
+    pub active: Option<PieceState>,
+    pub hold: Option<BlockName>,
+    pub can_hold: bool,
+    pub next: Vec<BlockName>,
+    // where the active block can be locked, only listed for `ActionSpace::Placements`
+    pub placements: Vec<PieceState>,
+}
+
+pub struct Env {
+    config: EnvConfig,
+    board: BitBoard,
+    queue: PieceQueue,
+    active: Option<MovableBlock>,
+    hold: Option<BlockName>,
+    hold_used: bool,
+    steps_since_gravity: u32,
+    placements: Vec<Placement>,
+}
+impl Env {
+    pub fn new(config: EnvConfig) -> Env {
+        let mut env = Env {
+            board: BitBoard::new(config.width, config.height),
+            queue: PieceQueue::new(0, config.pieces, Randomizer::Random),
+            config,
+            active: None,
+            hold: None,
+            hold_used: false,
+            steps_since_gravity: 0,
+            placements: Vec::new(),
+        };
+        env.reset(0);
+        env
+    }
+
+    pub fn reset(&mut self, seed: u64) -> Observation {
+        self.board = BitBoard::new(self.config.width, self.config.height);
+        self.queue = PieceQueue::new(seed, self.config.pieces, Randomizer::Random);
+        self.hold = None;
+        self.hold_used = false;
+        self.steps_since_gravity = 0;
+
+        let next = self.queue.pop();
+        self.spawn(next);
+        self.observation()
+    }
+
+    // returns the observation after the action, the reward for it (the number of lines
+    // it cleared, or `INVALID_PLACE_REWARD`) and whether the game is over
+    pub fn step(&mut self, action: Action) -> (Observation, f32, bool) {
+        let active = match &self.active {
+            Some(active) => active.clone(),
+            None => return (self.observation(), 0., true),
+        };
+
+        let lines = match action {
+            Action::Move(mv) => {
+                if let Some(moved) = apply_move(&self.board, &active, mv) {
+                    self.active = Some(moved);
+                }
+                self.apply_gravity()
+            }
+            Action::HardDrop => {
+                let dropped = apply_move(&self.board, &active, Move::SoftDrop).unwrap_or(active);
+                self.lock(&dropped)
+            }
+            Action::Hold => {
+                if !self.hold_used {
+                    let swap_in = self.hold.replace(active.name());
+                    let next = swap_in.unwrap_or_else(|| self.queue.pop());
+                    self.spawn(next);
+                    self.hold_used = true;
+                }
+                0
+            }
+            Action::Place(idx) => match self.placements.get(idx) {
+                Some(placement) => {
+                    let block = placement.block.clone();
+                    self.lock(&block)
+                }
+                None => {
+                    self.active = None;
+                    return (self.observation(), INVALID_PLACE_REWARD, true);
+                }
+            },
+        };
+
+        (self.observation(), lines as f32, self.active.is_none())
+    }
+
+    pub fn observation(&self) -> Observation {
+        let grid = self
+            .board
+            .rows()
+            .iter()
+            .flat_map(|&row| (0..self.board.width()).map(move |col| row & (1 << col) != 0))
+            .collect();
+
+        Observation {
+            width: self.board.width(),
+            height: self.board.height(),
+            grid,
+            active: self.active.as_ref().map(PieceState::of),
+            hold: self.hold,
+            can_hold: !self.hold_used,
+            next: self.queue.upcoming().collect(),
+            placements: self
+                .placements
+                .iter()
+                .map(|placement| PieceState::of(&placement.block))
+                .collect(),
+        }
+    }
+
+    pub fn board(&self) -> &BitBoard {
+        &self.board
+    }
+
+    // the moves which take the active block to each of the listed placements
+    pub fn placements(&self) -> &[Placement] {
+        &self.placements
+    }
+
+    fn apply_gravity(&mut self) -> usize {
+        let every = match self.config.gravity_steps {
+            Some(every) => every,
+            None => return 0,
+        };
+        self.steps_since_gravity += 1;
+        if self.steps_since_gravity < every {
+            return 0;
+        }
+        self.steps_since_gravity = 0;
+
+        let active = self.active.clone().unwrap();
+        if is_resting(&self.board, &active) {
+            self.lock(&active)
+        } else {
+            self.active = Some(active.move_relative((0, -1).into()));
+            0
+        }
+    }
+
+    fn lock(&mut self, block: &MovableBlock) -> usize {
+        let lines = self.board.lock(block);
+        self.hold_used = false;
+        self.steps_since_gravity = 0;
+        let next = self.queue.pop();
+        self.spawn(next);
+        lines
+    }
+
+    // the game is over when there's no room for the new block
+    fn spawn(&mut self, name: BlockName) {
+        let block = name.create_movable(self.board.spawn_position());
+        if !self.board.can_place(&block) {
+            self.active = None;
+            self.placements.clear();
+            return;
+        }
+
+        self.placements = match self.config.action_space {
+            ActionSpace::Placements => reachable_placements(&self.board, &block),
+            ActionSpace::Inputs => Vec::new(),
+        };
+        self.active = Some(block);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{Action, ActionSpace, Env, EnvConfig, INVALID_PLACE_REWARD};
+    use crate::tetris_block::move_gen::Move;
+
+    #[test]
+    fn test_plays_to_the_end() {
+        let mut env = Env::new(EnvConfig::default());
+        let obs = env.reset(7);
+        assert!(obs.active.is_some());
+        assert!(!obs.placements.is_empty());
+        assert_eq!(obs.grid.len(), obs.width * obs.height);
+
+        // always taking the first placement piles blocks up on the left
+        let mut steps = 0;
+        loop {
+            let (obs, _, done) = env.step(Action::Place(0));
+            steps += 1;
+            if done {
+                assert!(obs.active.is_none());
+                break;
+            }
+            assert!(steps < 1000);
+        }
+
+        // and once it's over, it stays over
+        assert!(env.step(Action::Place(0)).2);
+    }
+
+    #[test]
+    fn test_same_seed_same_game() {
+        let mut a = Env::new(EnvConfig::default());
+        let mut b = Env::new(EnvConfig::default());
+        assert_eq!(a.reset(3).next, b.reset(3).next);
+    }
+
+    #[test]
+    fn test_invalid_place() {
+        let mut env = Env::new(EnvConfig::default());
+        let obs = env.reset(2);
+        let (obs, reward, done) = env.step(Action::Place(obs.placements.len()));
+        assert_eq!(reward, INVALID_PLACE_REWARD);
+        assert!(done);
+        assert!(obs.active.is_none());
+    }
+
+    #[test]
+    fn test_inputs() {
+        let mut env = Env::new(EnvConfig {
+            action_space: ActionSpace::Inputs,
+            gravity_steps: None,
+            ..EnvConfig::default()
+        });
+        let obs = env.reset(1);
+        let start = obs.active.unwrap().position;
+        assert!(obs.placements.is_empty());
+
+        let (obs, _, _) = env.step(Action::Move(Move::Left));
+        assert_eq!(obs.active.unwrap().position, start - bevy::math::IVec2::X);
+
+        let (obs, _, _) = env.step(Action::HardDrop);
+        assert_eq!(obs.grid.iter().filter(|&&filled| filled).count(), 4);
+    }
+}
@@ -0,0 +1,808 @@
+// fumen (https://harddrop.com/fumen/) is how setups get shared: a 10 wide field and a
+// piece per page, packed into a string like `v115@vhAAgH`. this reads and writes v115
+// strings, and has keys to load a position in and print one (or the whole game) out.
+//
+// each page's field is stored as the difference from the last page's, after its piece
+// was locked in and any lines cleared, run length encoded. numbers are written in base
+// 64, least significant digit first.
+
+use std::collections::VecDeque;
+
+use bevy::prelude::*;
+
+use super::{
+    board::{Board, Cell, Fill},
+    check_skate_timer,
+    movable_block::{BlockName, MovableBlock, RotDir},
+    place_block,
+    skin::Skin,
+    spawn_block, spawn_cell, BlockPlaced, Ghost, Player, TetrisBlock,
+};
+
+pub const FIELD_WIDTH: usize = 10;
+pub const FIELD_HEIGHT: usize = 23;
+// the field, and the row below it that garbage rises from
+const FIELD_CELLS: usize = (FIELD_HEIGHT + 1) * FIELD_WIDTH;
+
+const PREFIX: &str = "v115@";
+const DIGITS: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+// the characters comments are written with, after being escaped like javascript's
+// escape() does
+const COMMENT_CHARS: &[u8] = b" !\"#$%&'()*+,-./0123456789:;<=>?@ABCDEFGHIJKLMNOPQRSTUVWXYZ[\\]^_`abcdefghijklmnopqrstuvwxyz{|}~";
+const COMMENT_BASE: u32 = COMMENT_CHARS.len() as u32 + 1;
+const MAX_COMMENT_LEN: usize = 4095;
+
+// how fumen numbers what's in a cell
+impl Fill {
+    fn to_num(fill: Option<Fill>) -> u32 {
+        match fill {
+            None => 0,
+            Some(Fill::Piece(name)) => piece_num(name),
+            Some(Fill::Garbage) => 8,
+        }
+    }
+
+    fn from_num(num: u32) -> Result<Option<Fill>, String> {
+        Ok(match num {
+            0 => None,
+            8 => Some(Fill::Garbage),
+            _ => Some(Fill::Piece(piece_name(num)?)),
+        })
+    }
+}
+
+fn piece_num(name: BlockName) -> u32 {
+    match name {
+        BlockName::I => 1,
+        BlockName::L => 2,
+        BlockName::O => 3,
+        BlockName::Z => 4,
+        BlockName::T => 5,
+        BlockName::J => 6,
+        BlockName::S => 7,
+        BlockName::Test => 8,
+    }
+}
+
+fn piece_name(num: u32) -> Result<BlockName, String> {
+    Ok(match num {
+        1 => BlockName::I,
+        2 => BlockName::L,
+        3 => BlockName::O,
+        4 => BlockName::Z,
+        5 => BlockName::T,
+        6 => BlockName::J,
+        7 => BlockName::S,
+        _ => return Err(format!("{} isn't a piece", num)),
+    })
+}
+
+// a fumen field, x from the left and y from the bottom. row -1 is below the field,
+// where rising garbage comes from.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Field {
+    // in the order they're encoded, from the top left
+    cells: Vec<Option<Fill>>,
+}
+impl Default for Field {
+    fn default() -> Self {
+        Field {
+            cells: vec![None; FIELD_CELLS],
+        }
+    }
+}
+impl Field {
+    fn to_idx(pos: IVec2) -> Option<usize> {
+        let in_field =
+            (0..FIELD_WIDTH as i32).contains(&pos.x) && (-1..FIELD_HEIGHT as i32).contains(&pos.y);
+        in_field.then(|| (FIELD_HEIGHT as i32 - 1 - pos.y) as usize * FIELD_WIDTH + pos.x as usize)
+    }
+
+    pub fn cell(&self, pos: IVec2) -> Option<Fill> {
+        Self::to_idx(pos).and_then(|idx| self.cells[idx])
+    }
+
+    pub fn set(&mut self, pos: IVec2, fill: Option<Fill>) {
+        if let Some(idx) = Self::to_idx(pos) {
+            self.cells[idx] = fill;
+        }
+    }
+
+    // the filled cells above the garbage row
+    pub fn filled(&self) -> impl Iterator<Item = (IVec2, Fill)> + '_ {
+        (0..FIELD_HEIGHT as i32).flat_map(move |y| {
+            (0..FIELD_WIDTH as i32).filter_map(move |x| {
+                let pos = IVec2::new(x, y);
+                self.cell(pos).map(|fill| (pos, fill))
+            })
+        })
+    }
+
+    // the board in the bottom left of the field
+    pub fn from_board(board: &Board) -> Result<Field, String> {
+        if board.width() > FIELD_WIDTH || board.height() > FIELD_HEIGHT {
+            return Err(format!(
+                "a {}x{} board doesn't fit in a fumen field",
+                board.width(),
+                board.height()
+            ));
+        }
+        let mut field = Field::default();
+        for (pos, cell) in board.iter_cells() {
+            field.set(pos, Some(cell.fill));
+        }
+        Ok(field)
+    }
+
+    fn row_filled(&self, y: i32) -> bool {
+        (0..FIELD_WIDTH as i32).all(|x| self.cell(IVec2::new(x, y)).is_some())
+    }
+
+    // what the next page starts from
+    fn after(&self, page: &Page) -> Field {
+        let mut field = self.clone();
+        if page.lock {
+            if let Some(piece) = &page.piece {
+                for pos in piece.positions() {
+                    field.set(pos, Some(Fill::Piece(piece.name())));
+                }
+            }
+            // filled rows come out and the ones above drop down
+            let mut y = 0;
+            while y < FIELD_HEIGHT as i32 {
+                if field.row_filled(y) {
+                    for above in y..FIELD_HEIGHT as i32 {
+                        for x in 0..FIELD_WIDTH as i32 {
+                            let fill = field.cell(IVec2::new(x, above + 1));
+                            field.set(IVec2::new(x, above), fill);
+                        }
+                    }
+                } else {
+                    y += 1;
+                }
+            }
+            if page.rise {
+                for y in (0..FIELD_HEIGHT as i32).rev() {
+                    for x in 0..FIELD_WIDTH as i32 {
+                        let fill = field.cell(IVec2::new(x, y - 1));
+                        field.set(IVec2::new(x, y), fill);
+                    }
+                }
+                for x in 0..FIELD_WIDTH as i32 {
+                    field.set(IVec2::new(x, -1), None);
+                }
+            }
+            if page.mirror {
+                for y in 0..FIELD_HEIGHT as i32 {
+                    for x in 0..FIELD_WIDTH as i32 / 2 {
+                        let (left, right) =
+                            (IVec2::new(x, y), IVec2::new(FIELD_WIDTH as i32 - 1 - x, y));
+                        let fill = field.cell(left);
+                        field.set(left, field.cell(right));
+                        field.set(right, fill);
+                    }
+                }
+            }
+        }
+        field
+    }
+}
+
+#[derive(Clone)]
+pub struct Page {
+    pub field: Field,
+    // the piece in play
+    pub piece: Option<MovableBlock>,
+    // the piece goes into the field for the next page, and any lines it fills clear
+    pub lock: bool,
+    // once locked in, the field goes up a row and the garbage row comes in under it
+    pub rise: bool,
+    // once locked in, the field is flipped left to right
+    pub mirror: bool,
+    pub comment: String,
+}
+impl Page {
+    pub fn new(field: Field, piece: Option<MovableBlock>) -> Page {
+        Page {
+            field,
+            piece,
+            lock: true,
+            rise: false,
+            mirror: false,
+            comment: String::new(),
+        }
+    }
+}
+
+pub fn encode(pages: &[Page]) -> String {
+    let mut out = Vec::new();
+    let mut prev_field = Field::default();
+    let mut prev_comment = String::new();
+    // where the count of unchanged pages after an unchanged field is, to add to it
+    let mut repeat_at = None;
+
+    for (idx, page) in pages.iter().enumerate() {
+        let runs = field_runs(&prev_field, &page.field);
+        if runs == [(8, FIELD_CELLS)] {
+            match repeat_at {
+                Some(at) if out[at] < 63 => out[at] += 1,
+                _ => {
+                    push_num(&mut out, 8 * FIELD_CELLS as u32 + FIELD_CELLS as u32 - 1, 2);
+                    repeat_at = Some(out.len());
+                    out.push(0);
+                }
+            }
+        } else {
+            repeat_at = None;
+            for (diff, count) in runs {
+                push_num(&mut out, diff * FIELD_CELLS as u32 + count as u32 - 1, 2);
+            }
+        }
+
+        let comment = escape(&page.comment);
+        let comment_changed = page.comment != prev_comment;
+        let (kind, rotation, pos) = match &page.piece {
+            Some(piece) => {
+                let (rotation, pos) = piece_location(piece);
+                (piece_num(piece.name()), rotation, pos)
+            }
+            None => (0, 0, 0),
+        };
+        let flags = [
+            !page.lock,
+            comment_changed,
+            // colours by piece, rather than all gray
+            idx == 0,
+            page.mirror,
+            page.rise,
+        ];
+        let action =
+            flags.iter().fold(0, |acc, &flag| acc * 2 + flag as u32) * FIELD_CELLS as u32 + pos;
+        push_num(&mut out, (action * 4 + rotation) * 8 + kind, 3);
+
+        if comment_changed {
+            let comment = &comment[..comment.len().min(MAX_COMMENT_LEN)];
+            push_num(&mut out, comment.len() as u32, 2);
+            for chunk in comment.as_bytes().chunks(4) {
+                let value = chunk.iter().rev().fold(0, |acc, &c| {
+                    let c = COMMENT_CHARS.iter().position(|&cc| cc == c).unwrap_or(0);
+                    acc * COMMENT_BASE + c as u32
+                });
+                push_num(&mut out, value, 5);
+            }
+            prev_comment = page.comment.clone();
+        }
+
+        prev_field = page.field.after(page);
+    }
+
+    let data: String = out.iter().map(|&d| DIGITS[d as usize] as char).collect();
+    // fumen breaks long strings up with a '?' after the first 42 characters, then
+    // every 47
+    let mut pieces = vec![&data[..data.len().min(42)]];
+    let mut rest = &data[data.len().min(42)..];
+    while !rest.is_empty() {
+        let (piece, after) = rest.split_at(rest.len().min(47));
+        pieces.push(piece);
+        rest = after;
+    }
+    format!("{}{}", PREFIX, pieces.join("?"))
+}
+
+pub fn decode(fumen: &str) -> Result<Vec<Page>, String> {
+    // anything before the version, like the viewer's url, is skipped
+    let start = fumen
+        .find(PREFIX)
+        .ok_or("only v115 fumen strings are supported")?;
+    let mut digits = Digits(
+        fumen[start + PREFIX.len()..]
+            .chars()
+            .filter(|&c| c != '?' && !c.is_whitespace())
+            .map(|c| {
+                DIGITS
+                    .iter()
+                    .position(|&d| d as char == c)
+                    .map(|d| d as u32)
+                    .ok_or_else(|| format!("{:?} can't be in a fumen string", c))
+            })
+            .collect::<Result<_, _>>()?,
+    );
+
+    let mut pages = Vec::new();
+    let mut field = Field::default();
+    let mut comment = String::new();
+    // pages left that keep the last field as is
+    let mut repeat = 0;
+    loop {
+        if repeat > 0 {
+            repeat -= 1;
+        } else {
+            let mut idx = 0;
+            while idx < FIELD_CELLS {
+                let run = digits.poll(2)?;
+                let (diff, count) = (run / FIELD_CELLS as u32, run as usize % FIELD_CELLS + 1);
+                if diff == 8 && count == FIELD_CELLS {
+                    repeat = digits.poll(1)?;
+                }
+                if idx + count > FIELD_CELLS || diff > 16 {
+                    return Err("the field runs over".to_string());
+                }
+                for cell in &mut field.cells[idx..idx + count] {
+                    let num = (Fill::to_num(*cell) + diff)
+                        .checked_sub(8)
+                        .ok_or("a cell in the field goes below empty")?;
+                    *cell = Fill::from_num(num)?;
+                }
+                idx += count;
+            }
+        }
+
+        let value = digits.poll(3)?;
+        let kind = value % 8;
+        let rotation = value / 8 % 4;
+        let pos = value / 32 % FIELD_CELLS as u32;
+        let flags = value / 32 / FIELD_CELLS as u32;
+        let [rise, mirror, _colour, comment_changed, not_lock] =
+            [0, 1, 2, 3, 4].map(|bit| (flags >> bit) & 1 == 1);
+
+        if comment_changed {
+            let len = digits.poll(2)? as usize;
+            let mut escaped = String::new();
+            while escaped.len() < len {
+                let mut value = digits.poll(5)?;
+                for _ in 0..4 {
+                    if escaped.len() < len {
+                        let c = COMMENT_CHARS
+                            .get((value % COMMENT_BASE) as usize)
+                            .ok_or("there's a character in a comment that can't be")?;
+                        escaped.push(*c as char);
+                    }
+                    value /= COMMENT_BASE;
+                }
+            }
+            comment = unescape(&escaped);
+        }
+
+        let piece = match kind {
+            0 => None,
+            kind => Some(piece_at(piece_name(kind)?, rotation, pos)?),
+        };
+        let page = Page {
+            field: field.clone(),
+            piece,
+            lock: !not_lock,
+            rise,
+            mirror,
+            comment: comment.clone(),
+        };
+        field = field.after(&page);
+        pages.push(page);
+
+        if digits.0.is_empty() {
+            return Ok(pages);
+        }
+    }
+}
+
+// what's left of a fumen string, as numbers
+struct Digits(VecDeque<u32>);
+impl Digits {
+    fn poll(&mut self, len: usize) -> Result<u32, String> {
+        let mut value = 0;
+        for scale in (0..len).map(|idx| 64u32.pow(idx as u32)) {
+            value += self.0.pop_front().ok_or("the fumen string ends too soon")? * scale;
+        }
+        Ok(value)
+    }
+}
+
+// each run of cells changed by the same amount, from the top left
+fn field_runs(prev: &Field, field: &Field) -> Vec<(u32, usize)> {
+    let mut runs: Vec<(u32, usize)> = Vec::new();
+    for (&before, &after) in prev.cells.iter().zip(&field.cells) {
+        let diff = Fill::to_num(after) + 8 - Fill::to_num(before);
+        match runs.last_mut() {
+            Some((last, count)) if *last == diff => *count += 1,
+            _ => runs.push((diff, 1)),
+        }
+    }
+    runs
+}
+
+fn push_num(out: &mut Vec<u32>, mut value: u32, digits: usize) {
+    for _ in 0..digits {
+        out.push(value % 64);
+        value /= 64;
+    }
+}
+
+// fumen rotations, by our rotation state. fumen numbers them reverse, right, spawn,
+// left.
+const ROTATIONS: [u32; 4] = [2, 1, 0, 3];
+
+// a piece's cells relative to where fumen says it is. that's the true rotation centre,
+// except fumen moves it so the o, and the i, s and z either way up, have the same
+// cells whichever rotation they're in.
+fn fumen_cells(name: BlockName, rotation: u32) -> [IVec2; 4] {
+    let spawn: [(i32, i32); 4] = match name {
+        BlockName::I => [(0, 0), (-1, 0), (1, 0), (2, 0)],
+        BlockName::T => [(0, 0), (-1, 0), (1, 0), (0, 1)],
+        BlockName::O => [(0, 0), (1, 0), (0, 1), (1, 1)],
+        BlockName::L => [(0, 0), (-1, 0), (1, 0), (1, 1)],
+        BlockName::J => [(0, 0), (-1, 0), (1, 0), (-1, 1)],
+        BlockName::S => [(0, 0), (-1, 0), (0, 1), (1, 1)],
+        BlockName::Z => [(0, 0), (1, 0), (0, 1), (-1, 1)],
+        BlockName::Test => [(0, 0); 4],
+    };
+    let shift = match (name, rotation) {
+        (BlockName::O, 0) => (1, 0),
+        (BlockName::O, 2) => (0, -1),
+        (BlockName::O, 3) => (1, -1),
+        (BlockName::I, 0) => (1, 0),
+        (BlockName::I, 3) => (0, -1),
+        (BlockName::S | BlockName::Z, 2) => (0, -1),
+        (BlockName::S, 1) => (-1, 0),
+        (BlockName::Z, 3) => (1, 0),
+        _ => (0, 0),
+    };
+    spawn.map(|(x, y)| {
+        let (x, y) = match rotation {
+            0 => (-x, -y),
+            1 => (y, -x),
+            2 => (x, y),
+            _ => (-y, x),
+        };
+        IVec2::new(x + shift.0, y + shift.1)
+    })
+}
+
+fn sorted(mut cells: Vec<IVec2>) -> Vec<IVec2> {
+    cells.sort_by_key(|pos| (pos.y, pos.x));
+    cells
+}
+
+// where fumen has a piece, as a rotation and a position in the field
+fn piece_location(piece: &MovableBlock) -> (u32, u32) {
+    let rotation = match piece.name() {
+        BlockName::O => 2,
+        _ => ROTATIONS[piece.rotation_state() % 4],
+    };
+    let cells = sorted(piece.positions().collect());
+    let offsets = sorted(fumen_cells(piece.name(), rotation).to_vec());
+    let at = cells[0] - offsets[0];
+    let pos = (FIELD_HEIGHT as i32 - 1 - at.y) * FIELD_WIDTH as i32 + at.x;
+    (rotation, pos.clamp(0, FIELD_CELLS as i32 - 1) as u32)
+}
+
+// our block covering the same cells as fumen's piece
+fn piece_at(name: BlockName, rotation: u32, pos: u32) -> Result<MovableBlock, String> {
+    let at = IVec2::new(
+        (pos as usize % FIELD_WIDTH) as i32,
+        FIELD_HEIGHT as i32 - 1 - (pos as usize / FIELD_WIDTH) as i32,
+    );
+    let cells = sorted(
+        fumen_cells(name, rotation)
+            .iter()
+            .map(|&offset| at + offset)
+            .collect(),
+    );
+    let rotation_state = ROTATIONS.iter().position(|&r| r == rotation).unwrap_or(0);
+    let mut piece = name.create_movable(IVec2::ZERO);
+    if piece.definition.rotations.len() > 1 {
+        for _ in 0..rotation_state {
+            piece = piece.rotate(RotDir::Right).0;
+        }
+    }
+    let piece = piece.move_relative(cells[0] - sorted(piece.positions().collect())[0]);
+    if sorted(piece.positions().collect()) != cells {
+        return Err(format!("couldn't place the {:?} piece", name));
+    }
+    Ok(piece)
+}
+
+// what javascript's escape() does, which fumen runs comments through
+fn escape(text: &str) -> String {
+    let mut out = String::new();
+    for c in text.chars() {
+        if c.is_ascii_alphanumeric() || "@*_+-./".contains(c) {
+            out.push(c);
+        } else if (c as u32) < 256 {
+            out.push_str(&format!("%{:02X}", c as u32));
+        } else {
+            for unit in c.encode_utf16(&mut [0; 2]) {
+                out.push_str(&format!("%u{:04X}", unit));
+            }
+        }
+    }
+    out
+}
+
+fn unescape(text: &str) -> String {
+    let mut units = Vec::new();
+    let mut rest = text;
+    while let Some(c) = rest.chars().next() {
+        let hex = |len: usize, from: usize| {
+            rest.get(from..from + len)
+                .and_then(|hex| u16::from_str_radix(hex, 16).ok())
+                .map(|unit| (unit, from + len))
+        };
+        let escaped = match c {
+            '%' if rest[1..].starts_with('u') => hex(4, 2),
+            '%' => hex(2, 1),
+            _ => None,
+        };
+        match escaped {
+            Some((unit, len)) => {
+                units.push(unit);
+                rest = &rest[len..];
+            }
+            None => {
+                units.push(c as u16);
+                rest = &rest[c.len_utf8()..];
+            }
+        }
+    }
+    String::from_utf16_lossy(&units)
+}
+
+// the pages of a player's game so far, one a placement
+#[derive(Component, Default)]
+struct Replay {
+    pages: Vec<Page>,
+    // the last position before a placement, to make the page from once it's placed
+    before_placing: Option<Page>,
+}
+
+// `import` is put on every board at the start. F prints the position of each player
+// and their game so far.
+pub struct FumenPlugin {
+    pub import: Option<Page>,
+}
+impl Plugin for FumenPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_startup_system(add_replays);
+        if let Some(page) = &self.import {
+            app.insert_resource(Import(page.clone()))
+                .add_startup_system(import_page);
+        }
+        app.add_system_to_stage(
+            "update_block_positions",
+            remember_position
+                .after(check_skate_timer)
+                .before(place_block),
+        )
+        .add_system_to_stage(
+            "update_block_positions",
+            record_placements.after(place_block),
+        )
+        .add_system(print_fumen);
+    }
+}
+
+struct Import(Page);
+
+fn add_replays(mut commands: Commands, players: Query<Entity, With<Player>>) {
+    for player in players.iter() {
+        commands.entity(player).insert(Replay::default());
+    }
+}
+
+fn import_page(
+    mut commands: Commands,
+    import: Res<Import>,
+    skin: Res<Skin>,
+    mut players: Query<(Entity, &Player, &mut Board)>,
+) {
+    let page = &import.0;
+    for (player, &Player { index }, mut board_state) in players.iter_mut() {
+        let (width, height) = (board_state.width() as i32, board_state.height() as i32);
+        let fits = |pos: IVec2| (0..width).contains(&pos.x) && (0..height).contains(&pos.y);
+        if page.field.filled().any(|(pos, _)| !fits(pos)) {
+            println!(
+                "fumen - the field doesn't fit on player {}'s board, skipping it",
+                index
+            );
+            continue;
+        }
+        let mut cells = Vec::new();
+        for (pos, fill) in page.field.filled() {
+            let color = fill.color();
+            let ent = spawn_cell(&mut commands, &skin, pos, fill, color);
+            *board_state.cell_mut(pos) = Some(Cell { ent, fill, color });
+            cells.push(ent);
+        }
+        commands.entity(player).push_children(&cells);
+
+        if let Some(piece) = &page.piece {
+            if piece.positions().all(fits) && board_state.can_place(piece) {
+                spawn_block(
+                    &mut commands,
+                    &skin,
+                    player,
+                    piece.clone(),
+                    Fill::Piece(piece.name()).color(),
+                );
+            } else {
+                println!(
+                    "fumen - no room for the {:?} piece on player {}'s board",
+                    piece.name(),
+                    index
+                );
+            }
+        }
+    }
+}
+
+// the page for where the player is now
+fn current_page(board_state: &Board, active: Option<&TetrisBlock>) -> Option<Page> {
+    let field = match Field::from_board(board_state) {
+        Ok(field) => field,
+        Err(err) => {
+            println!("fumen - {}", err);
+            return None;
+        }
+    };
+    Some(Page::new(field, active.map(|block| block.movable.clone())))
+}
+
+fn active_block<'a>(
+    active_query: &'a Query<(&Parent, &TetrisBlock), Without<Ghost>>,
+    player: Entity,
+) -> Option<&'a TetrisBlock> {
+    active_query
+        .iter()
+        .find(|(parent, _)| parent.0 == player)
+        .map(|(_, block)| block)
+}
+
+fn remember_position(
+    mut players: Query<(Entity, &Board, &mut Replay)>,
+    active_query: Query<(&Parent, &TetrisBlock), Without<Ghost>>,
+) {
+    for (player, board_state, mut replay) in players.iter_mut() {
+        replay.before_placing = current_page(board_state, active_block(&active_query, player));
+    }
+}
+
+fn record_placements(mut placed: EventReader<BlockPlaced>, mut players: Query<&mut Replay>) {
+    for &BlockPlaced { player, .. } in placed.iter() {
+        if let Ok(mut replay) = players.get_mut(player) {
+            if let Some(page) = replay.before_placing.take() {
+                replay.pages.push(page);
+            }
+        }
+    }
+}
+
+fn print_fumen(
+    kb: Res<Input<KeyCode>>,
+    players: Query<(Entity, &Player, &Board, &Replay)>,
+    active_query: Query<(&Parent, &TetrisBlock), Without<Ghost>>,
+) {
+    if !kb.just_pressed(KeyCode::F) {
+        return;
+    }
+    for (player, &Player { index }, board_state, replay) in players.iter() {
+        let now = current_page(board_state, active_block(&active_query, player));
+        if let Some(mut now) = now {
+            now.lock = false;
+            println!("fumen - player {} position: {}", index, encode(&[now]));
+        }
+        if !replay.pages.is_empty() {
+            println!(
+                "fumen - player {} game ({} pages): {}",
+                index,
+                replay.pages.len(),
+                encode(&replay.pages)
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use bevy::math::IVec2;
+
+    use crate::tetris_block::movable_block::{BlockName, RotDir};
+
+    use super::{decode, encode, escape, unescape, Field, Fill, Page};
+
+    #[test]
+    fn test_empty() {
+        let pages = decode("v115@vhAAgH").unwrap();
+        assert_eq!(pages.len(), 1);
+        assert_eq!(pages[0].field, Field::default());
+        assert!(pages[0].piece.is_none());
+        assert_eq!(encode(&pages), "v115@vhAAgH");
+
+        // a perfect clear setup, six wide and four high on the left
+        let fumen = "v115@9gF8DeF8DeF8DeF8NeAgH";
+        let pages = decode(fumen).unwrap();
+        let field = &pages[0].field;
+        assert_eq!(field.filled().count(), 24);
+        assert_eq!(field.cell(IVec2::new(5, 3)), Some(Fill::Garbage));
+        assert_eq!(field.cell(IVec2::new(6, 3)), None);
+        assert_eq!(field.cell(IVec2::new(0, 4)), None);
+        assert_eq!(encode(&pages), fumen);
+
+        let pages = decode("v115@vhCAgHAAAAAA").unwrap();
+        assert_eq!(pages.len(), 3);
+        assert_eq!(encode(&pages), "v115@vhCAgHAAAAAA");
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let mut field = Field::default();
+        for x in 0..9 {
+            field.set(IVec2::new(x, 0), Some(Fill::Garbage));
+        }
+        field.set(IVec2::new(3, 1), Some(Fill::Piece(BlockName::S)));
+        let mut first = Page::new(field, Some(BlockName::I.create_movable((8, 2).into())));
+        let (right, _) = BlockName::I
+            .create_movable((9, 2).into())
+            .rotate(RotDir::Right);
+        first.piece = Some(right);
+        first.comment = "well, it's 100% an i".to_string();
+
+        // the i goes down the well and clears the bottom row
+        let mut second = Page::new(first.field.after(&first), None);
+        second.lock = false;
+        let (t, _) = BlockName::T
+            .create_movable((4, 5).into())
+            .rotate(RotDir::Left);
+        second.piece = Some(t);
+
+        let pages = [first.clone(), second, first];
+        let fumen = encode(&pages);
+        let decoded = decode(&fumen).unwrap();
+        assert_eq!(decoded.len(), 3);
+        for (page, decoded) in pages.iter().zip(&decoded) {
+            assert_eq!(decoded.field, page.field);
+            assert_eq!(decoded.lock, page.lock);
+            assert_eq!(decoded.comment, page.comment);
+            let cells = |page: &Page| {
+                let piece = page.piece.as_ref().unwrap();
+                let mut cells: Vec<_> = piece.positions().collect();
+                cells.sort_by_key(|pos| (pos.y, pos.x));
+                (piece.name(), cells)
+            };
+            assert_eq!(cells(decoded), cells(page));
+        }
+        assert_eq!(decoded[1].field.cell(IVec2::new(0, 0)), None);
+        assert_eq!(
+            decoded[1].field.cell(IVec2::new(3, 0)),
+            Some(Fill::Piece(BlockName::S))
+        );
+        assert_eq!(encode(&decoded), fumen);
+    }
+
+    #[test]
+    fn test_piece_positions() {
+        // a t in the bottom left corner
+        let t = |rot| {
+            let pages = decode(&format!("v115@vh{}", rot)).unwrap();
+            let mut cells: Vec<_> = pages[0].piece.as_ref().unwrap().positions().collect();
+            cells.sort_by_key(|pos| (pos.y, pos.x));
+            cells
+        };
+        let cells = |cells: &[(i32, i32)]| -> Vec<IVec2> {
+            cells.iter().map(|&(x, y)| IVec2::new(x, y)).collect()
+        };
+        // spawn, at 1,0
+        assert_eq!(t("A1OJ"), cells(&[(0, 0), (1, 0), (2, 0), (1, 1)]));
+        // reverse, at 1,1
+        assert_eq!(t("AlJJ"), cells(&[(1, 0), (0, 1), (1, 1), (2, 1)]));
+
+        for code in ["A1OJ", "AlJJ"] {
+            let fumen = format!("v115@vh{}", code);
+            assert_eq!(encode(&decode(&fumen).unwrap()), fumen);
+        }
+    }
+
+    #[test]
+    fn test_escape() {
+        assert_eq!(escape("a b%"), "a%20b%25");
+        assert_eq!(escape("é"), "%E9");
+        assert_eq!(unescape(&escape("a b% é ✓")), "a b% é ✓");
+    }
+}
@@ -0,0 +1,325 @@
+use std::collections::VecDeque;
+
+use bevy::prelude::*;
+use rand::{thread_rng, Rng, SeedableRng};
+use rand_chacha::ChaCha12Rng;
+use serde::{Deserialize, Serialize};
+
+use super::{
+    board::{Board, Fill},
+    cell_positioning::AbsolutePositionedCell,
+    line_clear::{LineClear, Spin},
+    skin::Skin,
+    spawn_cell, spawn_new_block, BlockPlaced,
+};
+
+// how many lines of garbage each kind of clear sends. insert a different one before
+// adding the plugin to change the rules.
+#[derive(Clone, Debug)]
+pub struct AttackTable {
+    // each of these are indexed by the number of lines cleared
+    pub lines: Vec<u32>,
+    pub t_spin: Vec<u32>,
+    pub t_spin_mini: Vec<u32>,
+    // extra lines, indexed by the combo count. the last entry is used for combos
+    // longer than the table.
+    pub combo: Vec<u32>,
+    pub back_to_back: u32,
+    pub perfect_clear: u32,
+}
+impl Default for AttackTable {
+    fn default() -> Self {
+        AttackTable {
+            lines: vec![0, 0, 1, 2, 4],
+            t_spin: vec![0, 2, 4, 6],
+            t_spin_mini: vec![0, 0, 1],
+            combo: vec![0, 0, 1, 1, 2, 2, 3, 3, 4, 4, 4, 5],
+            back_to_back: 1,
+            perfect_clear: 10,
+        }
+    }
+}
+impl AttackTable {
+    pub fn attack(&self, clear: &LineClear) -> u32 {
+        if clear.lines == 0 {
+            return 0;
+        }
+
+        fn lookup(table: &[u32], idx: usize) -> u32 {
+            table
+                .get(idx)
+                .or_else(|| table.last())
+                .copied()
+                .unwrap_or(0)
+        }
+
+        let base = match clear.spin {
+            Spin::None => lookup(&self.lines, clear.lines),
+            Spin::Mini => lookup(&self.t_spin_mini, clear.lines),
+            Spin::Full => lookup(&self.t_spin, clear.lines),
+        };
+        let mut attack = base + lookup(&self.combo, clear.combo as usize);
+        if clear.back_to_back {
+            attack += self.back_to_back;
+        }
+        if clear.perfect_clear {
+            attack += self.perfect_clear;
+        }
+        attack
+    }
+}
+
+// garbage sent by the opponent, waiting to be added to the board
+#[derive(Component, Clone, Serialize, Deserialize)]
+pub struct GarbageQueue {
+    rng: ChaCha12Rng,
+    // lines per attack, oldest first
+    incoming: VecDeque<u32>,
+}
+impl GarbageQueue {
+    pub fn new(seed: u64) -> GarbageQueue {
+        GarbageQueue {
+            rng: ChaCha12Rng::seed_from_u64(seed),
+            incoming: VecDeque::new(),
+        }
+    }
+
+    pub fn push(&mut self, lines: u32) {
+        if lines > 0 {
+            self.incoming.push_back(lines);
+        }
+    }
+
+    pub fn pending(&self) -> u32 {
+        self.incoming.iter().sum()
+    }
+
+    // an attack first cancels out incoming garbage, oldest first. returns what's left
+    // of the attack to send on.
+    pub fn cancel(&mut self, mut attack: u32) -> u32 {
+        while attack > 0 {
+            let lines = match self.incoming.front_mut() {
+                Some(lines) => lines,
+                None => break,
+            };
+            let cancelled = attack.min(*lines);
+            attack -= cancelled;
+            *lines -= cancelled;
+            if *lines == 0 {
+                self.incoming.pop_front();
+            }
+        }
+        attack
+    }
+
+    // rows to start a game with, bottom up. messiness is the chance of each row's hole
+    // moving from the one below it.
+    pub fn messy_rows(&mut self, rows: usize, width: usize, messiness: f32) -> Vec<usize> {
+        let mut hole = self.rng.gen_range(0..width);
+        (0..rows)
+            .map(|_| {
+                if self.rng.gen::<f32>() < messiness {
+                    // somewhere other than right above the last one
+                    hole = (hole + self.rng.gen_range(1..width)) % width;
+                }
+                hole
+            })
+            .collect()
+    }
+
+    // empties the queue, returning the hole column of each garbage row from the bottom
+    // up. the rows from one attack share a hole, and the oldest attack ends up on top.
+    pub fn take_rows(&mut self, width: usize) -> Vec<usize> {
+        let mut holes = Vec::new();
+        while let Some(lines) = self.incoming.pop_back() {
+            let hole = self.rng.gen_range(0..width);
+            holes.extend((0..lines).map(|_| hole));
+        }
+        holes
+    }
+}
+
+// lines of garbage left over after cancelling, for the player's opponents
+pub struct OutgoingAttack {
+    pub player: Entity,
+    pub lines: u32,
+}
+
+pub struct GarbagePlugin {
+    // G sends garbage to everyone, off online where the other end wouldn't know
+    pub debug_keys: bool,
+}
+impl Plugin for GarbagePlugin {
+    fn build(&self, app: &mut App) {
+        if self.debug_keys {
+            app.add_system(queue_debug_garbage);
+        }
+        app.init_resource::<AttackTable>()
+            .add_event::<OutgoingAttack>()
+            // garbage goes in between placing a block and spawning the next one, once
+            // the cells of the placed block have been given their board positions
+            .add_system_to_stage("spawn_new_blocks", apply_garbage.before(spawn_new_block))
+            .add_system_to_stage("spawn_new_blocks", send_garbage.after(apply_garbage));
+    }
+}
+
+fn queue_debug_garbage(kb: Res<Input<KeyCode>>, mut query: Query<&mut GarbageQueue>) {
+    if kb.just_pressed(KeyCode::G) {
+        let lines = thread_rng().gen_range(1..=4);
+        for mut queue in query.iter_mut() {
+            queue.push(lines);
+            println!(
+                "queued {} lines of garbage, {} pending",
+                lines,
+                queue.pending()
+            );
+        }
+    }
+}
+
+fn apply_garbage(
+    mut commands: Commands,
+    mut placed: EventReader<BlockPlaced>,
+    mut outgoing: EventWriter<OutgoingAttack>,
+    table: Res<AttackTable>,
+    skin: Res<Skin>,
+    mut players: Query<(&mut GarbageQueue, &mut Board)>,
+    mut cell_query: Query<&mut AbsolutePositionedCell>,
+) {
+    for &BlockPlaced { player, clear } in placed.iter() {
+        let (mut queue, mut board_state) = match players.get_mut(player) {
+            Ok(ok) => ok,
+            Err(_) => continue,
+        };
+
+        // clearing lines holds off incoming garbage, which only arrives once a
+        // block is placed without clearing anything
+        if clear.lines > 0 {
+            let lines = queue.cancel(table.attack(&clear));
+            if lines > 0 {
+                println!("sending {} lines of garbage", lines);
+                outgoing.send(OutgoingAttack { player, lines });
+            }
+            continue;
+        }
+
+        let holes = queue.take_rows(board_state.width());
+        if holes.is_empty() {
+            continue;
+        }
+        println!("adding {} lines of garbage", holes.len());
+        add_garbage_rows(
+            &mut commands,
+            &skin,
+            player,
+            &mut board_state,
+            &holes,
+            &mut cell_query,
+        );
+    }
+}
+
+// pushes rows of garbage in from the bottom of the player's board, with a hole in
+// each at the given column
+pub fn add_garbage_rows(
+    commands: &mut Commands,
+    skin: &Skin,
+    player: Entity,
+    board_state: &mut Board,
+    holes: &[usize],
+    cell_query: &mut Query<&mut AbsolutePositionedCell>,
+) {
+    let mut spawned = Vec::new();
+    let (pushed_out, moved) = board_state.insert_garbage_rows(holes, |pos| {
+        let ent = spawn_cell(commands, skin, pos, Fill::Garbage, Fill::Garbage.color());
+        spawned.push(ent);
+        ent
+    });
+    commands.entity(player).push_children(&spawned);
+    for ent in pushed_out {
+        commands.entity(ent).despawn_recursive();
+    }
+    for (ent, pos) in moved {
+        if let Ok(mut c) = cell_query.get_component_mut::<AbsolutePositionedCell>(ent) {
+            c.pos = pos;
+        }
+    }
+}
+
+// everyone else gets the garbage a player sends
+fn send_garbage(
+    mut outgoing: EventReader<OutgoingAttack>,
+    mut players: Query<(Entity, &mut GarbageQueue)>,
+) {
+    for attack in outgoing.iter() {
+        for (player, mut queue) in players.iter_mut() {
+            if player != attack.player {
+                queue.push(attack.lines);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::tetris_block::line_clear::{LineClear, Spin};
+
+    use super::{AttackTable, GarbageQueue};
+
+    #[test]
+    fn test_attack_table() {
+        let table = AttackTable::default();
+        let clear = |lines, spin| LineClear {
+            lines,
+            spin,
+            perfect_clear: false,
+            combo: 0,
+            back_to_back: false,
+        };
+
+        assert_eq!(table.attack(&clear(0, Spin::Full)), 0);
+        assert_eq!(table.attack(&clear(1, Spin::None)), 0);
+        assert_eq!(table.attack(&clear(4, Spin::None)), 4);
+        assert_eq!(table.attack(&clear(2, Spin::Full)), 4);
+        assert_eq!(table.attack(&clear(1, Spin::Mini)), 0);
+        assert_eq!(
+            table.attack(&LineClear {
+                combo: 20,
+                back_to_back: true,
+                ..clear(4, Spin::None)
+            }),
+            4 + 5 + 1
+        );
+    }
+
+    #[test]
+    fn test_cancel() {
+        let mut queue = GarbageQueue::new(0);
+        queue.push(2);
+        queue.push(3);
+        assert_eq!(queue.cancel(3), 0);
+        assert_eq!(queue.pending(), 2);
+        assert_eq!(queue.cancel(4), 2);
+        assert_eq!(queue.pending(), 0);
+
+        queue.push(2);
+        queue.push(1);
+        let holes = queue.take_rows(8);
+        assert_eq!(holes.len(), 3);
+        // the older attack's rows share a hole, on top
+        assert_eq!(holes[1], holes[2]);
+        assert!(queue.take_rows(8).is_empty());
+    }
+
+    #[test]
+    fn test_messy_rows() {
+        let mut queue = GarbageQueue::new(0);
+        let clean = queue.messy_rows(10, 8, 0.);
+        assert_eq!(clean.len(), 10);
+        assert!(clean.iter().all(|&hole| hole == clean[0]));
+
+        let messy = queue.messy_rows(10, 8, 1.);
+        assert!(messy.iter().all(|&hole| hole < 8));
+        assert!(messy.windows(2).all(|pair| pair[0] != pair[1]));
+    }
+}
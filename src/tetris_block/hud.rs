@@ -0,0 +1,247 @@
+// the panel to the right of each board, with what's in hold, the next pieces and how
+// the player is doing. the text is bevy ui, laid out in pixels from the top left of
+// the window, and the pieces are sprites lined up with it.
+
+use std::time::Duration;
+
+use bevy::prelude::*;
+
+use crate::{BOARD_GAP, CELL_SIDE_LEN, HUD_WIDTH};
+
+use super::{
+    board::Fill,
+    mode::{format_time, Mode, Progress, FONT},
+    movable_block::BlockName,
+    piece_queue::{PieceQueue, PREVIEW_LEN},
+    settings::Settings,
+    skin::Skin,
+    Hold, Player,
+};
+
+const FONT_SIZE: f32 = 16.;
+const MARGIN: f32 = 10.;
+// the cells of the hold and next pieces
+const PREVIEW_CELL: f32 = CELL_SIDE_LEN * 0.4;
+// from the top of the window, the labels and the middle of each piece under them
+const HOLD_TOP: f32 = 10.;
+const HOLD_PIECE: f32 = 60.;
+const NEXT_TOP: f32 = 100.;
+const NEXT_PIECE: f32 = 140.;
+const NEXT_SPACING: f32 = 44.;
+
+pub struct HudPlugin;
+impl Plugin for HudPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_startup_system(spawn_hud)
+            .add_system(update_stats)
+            .add_system(update_previews);
+    }
+}
+
+// the text with a player's score, level, lines, time and pieces per second
+#[derive(Component)]
+struct Stats {
+    player: Entity,
+}
+
+// a piece shown in hold or next, not children of the player so they outlive restarts
+#[derive(Component)]
+struct Preview {
+    player: Entity,
+}
+
+// where a player's panel starts, in pixels from the left of the window
+fn panel_left(index: usize, board_width: usize) -> f32 {
+    let board_width = board_width as f32 * CELL_SIDE_LEN;
+    index as f32 * (board_width + HUD_WIDTH + BOARD_GAP) + board_width
+}
+
+fn text(value: &str, style: TextStyle, position: Rect<Val>) -> TextBundle {
+    TextBundle {
+        style: Style {
+            position_type: PositionType::Absolute,
+            position,
+            ..default()
+        },
+        text: Text::with_section(value, style, default()),
+        ..default()
+    }
+}
+
+fn spawn_hud(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    settings: Res<Settings>,
+    players: Query<(Entity, &Player)>,
+) {
+    let style = TextStyle {
+        font: asset_server.load(FONT),
+        font_size: FONT_SIZE,
+        color: Color::WHITE,
+    };
+    for (player, &Player { index }) in players.iter() {
+        let left = Val::Px(panel_left(index, settings.board_width) + MARGIN);
+        for (label, top) in [("HOLD", HOLD_TOP), ("NEXT", NEXT_TOP)] {
+            commands.spawn_bundle(text(
+                label,
+                style.clone(),
+                Rect {
+                    left,
+                    top: Val::Px(top),
+                    ..default()
+                },
+            ));
+        }
+        commands
+            .spawn_bundle(text(
+                "",
+                style.clone(),
+                Rect {
+                    left,
+                    bottom: Val::Px(MARGIN),
+                    ..default()
+                },
+            ))
+            .insert(Stats { player });
+    }
+}
+
+fn stats_text(timed: bool, progress: &Progress) -> String {
+    let (time, pps) = if timed {
+        let pps = if progress.elapsed > Duration::ZERO {
+            format!(
+                "{:.2}",
+                progress.pieces as f32 / progress.elapsed.as_secs_f32()
+            )
+        } else {
+            "0.00".to_string()
+        };
+        (format_time(progress.elapsed), pps)
+    } else {
+        ("-".to_string(), "-".to_string())
+    };
+    format!(
+        "SCORE {}\nLEVEL {}\nLINES {}\nTIME  {}\nPPS   {}",
+        progress.score,
+        progress.level.max(1),
+        progress.lines,
+        time,
+        pps
+    )
+}
+
+fn update_stats(mode: Res<Mode>, players: Query<&Progress>, mut texts: Query<(&mut Text, &Stats)>) {
+    for (mut text, stats) in texts.iter_mut() {
+        let progress = match players.get(stats.player) {
+            Ok(progress) => progress,
+            Err(_) => continue,
+        };
+        let value = stats_text(mode.0.timed(), progress);
+        // only when it's changed, so the text isn't laid out again every frame
+        if text.sections[0].value != value {
+            text.sections[0].value = value;
+        }
+    }
+}
+
+#[allow(clippy::type_complexity)]
+fn update_previews(
+    mut commands: Commands,
+    settings: Res<Settings>,
+    skin: Res<Skin>,
+    players: Query<
+        (Entity, &Transform, &Hold, &PieceQueue),
+        Or<(Changed<Hold>, Changed<PieceQueue>)>,
+    >,
+    previews: Query<(Entity, &Preview)>,
+) {
+    let window_top = settings.board_height as f32 * CELL_SIDE_LEN / 2.;
+    let panel_middle = settings.board_width as f32 * CELL_SIDE_LEN / 2. + HUD_WIDTH / 2.;
+    for (player, transform, hold, piece_queue) in players.iter() {
+        for (ent, preview) in previews.iter() {
+            if preview.player == player {
+                commands.entity(ent).despawn_recursive();
+            }
+        }
+
+        let at = |top: f32| transform.translation + Vec3::new(panel_middle, window_top - top, 20.);
+        if let Some(piece) = hold.piece {
+            // greyed out until it can be used again
+            let color = if hold.used || hold.disabled {
+                Color::DARK_GRAY
+            } else {
+                skin.tint(Fill::Piece(piece), Fill::Piece(piece).color())
+            };
+            spawn_preview(&mut commands, &skin, player, piece, at(HOLD_PIECE), color);
+        }
+        for (idx, piece) in piece_queue.upcoming().take(PREVIEW_LEN).enumerate() {
+            let top = NEXT_PIECE + idx as f32 * NEXT_SPACING;
+            spawn_preview(
+                &mut commands,
+                &skin,
+                player,
+                piece,
+                at(top),
+                skin.tint(Fill::Piece(piece), Fill::Piece(piece).color()),
+            );
+        }
+    }
+}
+
+fn spawn_preview(
+    commands: &mut Commands,
+    skin: &Skin,
+    player: Entity,
+    piece: BlockName,
+    translation: Vec3,
+    color: Color,
+) {
+    let block = piece.create_movable(IVec2::ZERO);
+    let cells: Vec<Vec2> = block
+        .relative_positions()
+        .map(|pos| pos.as_vec2())
+        .collect();
+    let min = cells.iter().copied().reduce(Vec2::min).unwrap_or_default();
+    let max = cells.iter().copied().reduce(Vec2::max).unwrap_or_default();
+    let middle = (min + max) / 2.;
+    commands
+        .spawn_bundle(TransformBundle::from_transform(
+            Transform::from_translation(translation),
+        ))
+        .insert(Preview { player })
+        .with_children(|builder| {
+            for cell in cells {
+                let at = ((cell - middle) * PREVIEW_CELL).extend(0.);
+                let fill = Fill::Piece(piece);
+                skin.spawn_tile(builder, fill, color, PREVIEW_CELL * 0.9, at);
+                skin.spawn_symbol(builder, fill, color, PREVIEW_CELL, at + Vec3::Z);
+            }
+        });
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::Duration;
+
+    use crate::tetris_block::mode::Progress;
+
+    use super::stats_text;
+
+    #[test]
+    fn test_stats_text() {
+        let progress = Progress {
+            score: 1200,
+            level: 3,
+            lines: 24,
+            pieces: 30,
+            elapsed: Duration::from_secs(20),
+            ..Progress::default()
+        };
+        assert_eq!(
+            stats_text(true, &progress),
+            "SCORE 1200\nLEVEL 3\nLINES 24\nTIME  0:20.000\nPPS   1.50"
+        );
+        assert!(stats_text(false, &progress).ends_with("TIME  -\nPPS   -"));
+        assert!(stats_text(true, &Progress::default()).ends_with("PPS   0.00"));
+    }
+}
@@ -0,0 +1,209 @@
+use bevy::{math::IVec2, prelude::Component};
+use serde::{Deserialize, Serialize};
+
+use super::{
+    movable_block::{BlockName, MovableBlock},
+    move_gen::{Collision, Move},
+};
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Spin {
+    None,
+    Mini,
+    Full,
+}
+
+// what happened when a block was locked, used to work out how much garbage it sends
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LineClear {
+    pub lines: usize,
+    pub spin: Spin,
+    pub perfect_clear: bool,
+    // how many placements in a row cleared lines before this one
+    pub combo: u32,
+    // this clear and the last one were both tetrises or spins
+    pub back_to_back: bool,
+}
+
+impl LineClear {
+    // points for the clear, as in the guideline games. spins with no lines still score.
+    pub fn score(&self, level: u32) -> u32 {
+        let lines = self.lines.min(4);
+        let base = match self.spin {
+            Spin::None => [0, 100, 300, 500, 800][lines],
+            Spin::Mini => [100, 200, 400, 400, 400][lines],
+            Spin::Full => [400, 800, 1200, 1600, 1600][lines],
+        };
+        let mut score = if self.back_to_back {
+            base * 3 / 2
+        } else {
+            base
+        };
+        if self.lines > 0 {
+            score += 50 * self.combo;
+        }
+        if self.perfect_clear {
+            score += [0, 800, 1200, 1800, 2000][lines];
+        }
+        score * level
+    }
+}
+
+// the combo and back to back state carried from one placement to the next
+#[derive(Component, Default, Clone, Debug, Serialize, Deserialize)]
+pub struct Streaks {
+    pub combo: u32,
+    pub back_to_back: bool,
+}
+impl Streaks {
+    pub fn record(&mut self, lines: usize, spin: Spin, perfect_clear: bool) -> LineClear {
+        if lines == 0 {
+            // placing without clearing breaks the combo, but not back to back
+            self.combo = 0;
+            return LineClear {
+                lines,
+                spin,
+                perfect_clear: false,
+                combo: 0,
+                back_to_back: false,
+            };
+        }
+
+        let difficult = lines >= 4 || spin != Spin::None;
+        let back_to_back = difficult && self.back_to_back;
+        self.back_to_back = difficult;
+
+        let combo = self.combo;
+        self.combo += 1;
+
+        LineClear {
+            lines,
+            spin,
+            perfect_clear,
+            combo,
+            back_to_back,
+        }
+    }
+}
+
+// the 3 corner rule: a T block that got into place by rotating, with 3 of the 4 cells
+// diagonal to its center filled (walls and floor count), is a T-spin. it's only a
+// mini if one of the two corners either side of the T's point is open.
+pub fn detect_spin(board: &impl Collision, block: &MovableBlock, last_move: Option<Move>) -> Spin {
+    if block.name() != BlockName::T
+        || !matches!(last_move, Some(Move::RotateLeft) | Some(Move::RotateRight))
+    {
+        return Spin::None;
+    }
+
+    let center = block.root_position();
+    let corners = [(-1, -1), (1, -1), (-1, 1), (1, 1)]
+        .into_iter()
+        .filter(|&corner| board.is_occupied(center + IVec2::from(corner)))
+        .count();
+    if corners < 3 {
+        return Spin::None;
+    }
+
+    // the first cell of the T's definition is its point
+    let point = block.relative_positions().next().unwrap();
+    let side = IVec2::new(point.y, point.x);
+    let front = [point + side, point - side]
+        .into_iter()
+        .filter(|&corner| board.is_occupied(center + corner))
+        .count();
+    if front == 2 {
+        Spin::Full
+    } else {
+        Spin::Mini
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::tetris_block::{
+        bit_board::BitBoard,
+        movable_block::{BlockName, RotDir},
+        move_gen::Move,
+    };
+
+    use super::{detect_spin, LineClear, Spin, Streaks};
+
+    #[test]
+    fn test_detect_spin() {
+        // ██..████
+        // ......██
+        // ██..████
+        let mut board = BitBoard::new(4, 4);
+        for pos in [(0, 0), (2, 0), (3, 0), (3, 1), (0, 2), (2, 2), (3, 2)] {
+            board.set_occupied(pos.into());
+        }
+
+        // pointing down into the slot
+        let t = BlockName::T
+            .create_movable((1, 1).into())
+            .rotate(RotDir::Right)
+            .0
+            .rotate(RotDir::Right)
+            .0;
+        assert!(board.can_place(&t));
+        assert_eq!(detect_spin(&board, &t, Some(Move::RotateRight)), Spin::Full);
+        assert_eq!(detect_spin(&board, &t, Some(Move::Left)), Spin::None);
+
+        // pointing up, with only one of the corners by its point filled
+        // ....████
+        // ........
+        // ██..██..
+        let mut board = BitBoard::new(4, 4);
+        for pos in [(0, 0), (2, 0), (2, 2), (3, 2)] {
+            board.set_occupied(pos.into());
+        }
+        let t = BlockName::T.create_movable((1, 1).into());
+        assert!(board.can_place(&t));
+        assert_eq!(detect_spin(&board, &t, Some(Move::RotateLeft)), Spin::Mini);
+    }
+
+    #[test]
+    fn test_streaks() {
+        let mut streaks = Streaks::default();
+        let tetris = streaks.record(4, Spin::None, false);
+        assert_eq!((tetris.combo, tetris.back_to_back), (0, false));
+
+        let spin = streaks.record(2, Spin::Full, false);
+        assert_eq!((spin.combo, spin.back_to_back), (1, true));
+
+        // a plain clear breaks back to back, a placement without one breaks the combo
+        let single = streaks.record(1, Spin::None, false);
+        assert_eq!((single.combo, single.back_to_back), (2, false));
+        streaks.record(0, Spin::None, false);
+        let single = streaks.record(1, Spin::None, false);
+        assert_eq!(single.combo, 0);
+    }
+
+    #[test]
+    fn test_score() {
+        let clear = |lines, spin| LineClear {
+            lines,
+            spin,
+            perfect_clear: false,
+            combo: 0,
+            back_to_back: false,
+        };
+        assert_eq!(clear(0, Spin::None).score(1), 0);
+        assert_eq!(clear(4, Spin::None).score(1), 800);
+        assert_eq!(clear(0, Spin::Full).score(1), 400);
+        assert_eq!(clear(2, Spin::Full).score(3), 3600);
+        let b2b_tetris = LineClear {
+            back_to_back: true,
+            combo: 2,
+            ..clear(4, Spin::None)
+        };
+        assert_eq!(b2b_tetris.score(1), 1200 + 100);
+        let pc = LineClear {
+            perfect_clear: true,
+            ..clear(1, Spin::None)
+        };
+        assert_eq!(pc.score(2), (100 + 800) * 2);
+    }
+}
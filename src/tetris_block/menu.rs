@@ -0,0 +1,697 @@
+// the screens around the game: the main menu, settings, the pause overlay and the
+// results once everyone's finished. the gameplay stages only step while playing.
+
+use std::time::Duration;
+
+use bevy::{app::AppExit, prelude::*};
+
+use super::{
+    controls::{any_pressed, take_action, ControlsConfig, InputAction, Layout, PressedActions},
+    delays::{ClearEffect, Delays},
+    mode::{mode_by_name, Finished, Mode, Restart, FONT, MODE_NAMES},
+    palette::Palette,
+    settings::Settings,
+    skin::{GhostStyle, SkinNames},
+    tweening_position::{Animation, Easing},
+    Player, Remote,
+};
+
+const FONT_SIZE: f32 = 24.;
+// how far the line clear and entry delays go up and down in settings
+const DELAY_STEP: Duration = Duration::from_millis(50);
+const MAX_DELAY: Duration = Duration::from_millis(1000);
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum GameState {
+    Menu,
+    Settings,
+    Controls,
+    Playing,
+    // the game is still there behind the overlay, just not stepping
+    Paused,
+    // every player has finished
+    GameOver,
+}
+
+pub struct MenuPlugin {
+    // the screen the game starts on
+    pub start: GameState,
+}
+impl Plugin for MenuPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_state(self.start)
+            .init_resource::<Selected>()
+            .insert_resource(Rebinding {
+                layout: Layout::Single,
+                waiting: None,
+            })
+            .add_system_set(SystemSet::on_enter(GameState::Menu).with_system(spawn_dimmed_screen))
+            .add_system_set(
+                SystemSet::on_update(GameState::Menu)
+                    .with_system(menu_input)
+                    .with_system(draw_menu.after(menu_input)),
+            )
+            .add_system_set(SystemSet::on_exit(GameState::Menu).with_system(despawn_screen))
+            .add_system_set(
+                SystemSet::on_enter(GameState::Settings).with_system(spawn_dimmed_screen),
+            )
+            .add_system_set(
+                SystemSet::on_update(GameState::Settings)
+                    .with_system(settings_input)
+                    .with_system(draw_settings.after(settings_input)),
+            )
+            .add_system_set(SystemSet::on_exit(GameState::Settings).with_system(save_settings))
+            .add_system_set(SystemSet::on_exit(GameState::Settings).with_system(despawn_screen))
+            .add_system_set(
+                SystemSet::on_enter(GameState::Controls).with_system(spawn_dimmed_screen),
+            )
+            .add_system_set(
+                SystemSet::on_update(GameState::Controls)
+                    .with_system(controls_input)
+                    .with_system(draw_controls.after(controls_input)),
+            )
+            .add_system_set(SystemSet::on_exit(GameState::Controls).with_system(save_controls))
+            .add_system_set(SystemSet::on_exit(GameState::Controls).with_system(despawn_screen))
+            .add_system_set(
+                SystemSet::on_update(GameState::Playing)
+                    .with_system(pause_game)
+                    .with_system(detect_game_over),
+            )
+            .add_system_set(SystemSet::on_enter(GameState::Paused).with_system(spawn_pause_screen))
+            .add_system_set(SystemSet::on_update(GameState::Paused).with_system(pause_input))
+            .add_system_set(SystemSet::on_exit(GameState::Paused).with_system(despawn_screen))
+            .add_system_set(
+                SystemSet::on_enter(GameState::GameOver).with_system(spawn_results_screen),
+            )
+            .add_system_set(
+                SystemSet::on_update(GameState::GameOver)
+                    .with_system(results_input)
+                    .with_system(play_again.after(results_input)),
+            )
+            .add_system_set(SystemSet::on_exit(GameState::GameOver).with_system(despawn_screen));
+    }
+}
+
+// which line of the menu or settings is picked
+#[derive(Default)]
+struct Selected(usize);
+
+// the layout being shown on the controls screen, and the action waiting for a key
+// or button to be pressed for it
+struct Rebinding {
+    layout: Layout,
+    waiting: Option<InputAction>,
+}
+
+// the whole of whichever screen is up, despawned on the way out of it
+#[derive(Component)]
+struct Screen;
+
+// the text of the screen, redrawn as the selection moves
+#[derive(Component)]
+struct ScreenText;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+enum MenuItem {
+    Play(&'static str),
+    Settings,
+    Controls,
+    Quit,
+}
+
+fn menu_items() -> Vec<MenuItem> {
+    MODE_NAMES
+        .iter()
+        .map(|&name| MenuItem::Play(name))
+        .chain([MenuItem::Settings, MenuItem::Controls, MenuItem::Quit])
+        .collect()
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+enum Setting {
+    Animation,
+    ClearEffect,
+    LineClearDelay,
+    EntryDelay,
+    Skin,
+    Ghost,
+    Palette,
+    Symbols,
+    Back,
+}
+
+const SETTINGS: &[Setting] = &[
+    Setting::Animation,
+    Setting::ClearEffect,
+    Setting::LineClearDelay,
+    Setting::EntryDelay,
+    Setting::Skin,
+    Setting::Ghost,
+    Setting::Palette,
+    Setting::Symbols,
+    Setting::Back,
+];
+
+// what the animation setting goes through, `None` being off
+const EASINGS: &[Option<Easing>] = &[
+    None,
+    Some(Easing::Linear),
+    Some(Easing::EaseOut),
+    Some(Easing::EaseInOut),
+];
+
+// a key press that changes screens, taken out of the input so the next screen,
+// which can run in the same frame, doesn't see it too
+fn take_key(kb: &mut Input<KeyCode>, key: KeyCode) -> bool {
+    let pressed = kb.just_pressed(key);
+    if pressed {
+        kb.reset(key);
+    }
+    pressed
+}
+
+// moves the selection up and down a list, wrapping round at the ends
+fn move_selection(kb: &Input<KeyCode>, selected: &mut Selected, len: usize) {
+    if kb.just_pressed(KeyCode::Up) {
+        selected.0 = (selected.0 + len - 1) % len;
+    }
+    if kb.just_pressed(KeyCode::Down) {
+        selected.0 = (selected.0 + 1) % len;
+    }
+}
+
+fn spawn_screen(
+    commands: &mut Commands,
+    asset_server: &AssetServer,
+    background: Color,
+    align: AlignItems,
+    value: &str,
+) {
+    commands
+        .spawn_bundle(NodeBundle {
+            style: Style {
+                size: Size::new(Val::Percent(100.), Val::Percent(100.)),
+                position_type: PositionType::Absolute,
+                justify_content: JustifyContent::Center,
+                align_items: align,
+                ..default()
+            },
+            color: background.into(),
+            ..default()
+        })
+        .insert(Screen)
+        .with_children(|builder| {
+            builder
+                .spawn_bundle(TextBundle {
+                    style: Style {
+                        margin: Rect::all(Val::Px(20.)),
+                        ..default()
+                    },
+                    text: Text::with_section(
+                        value,
+                        TextStyle {
+                            font: asset_server.load(FONT),
+                            font_size: FONT_SIZE,
+                            color: Color::WHITE,
+                        },
+                        TextAlignment {
+                            horizontal: HorizontalAlign::Center,
+                            ..default()
+                        },
+                    ),
+                    ..default()
+                })
+                .insert(ScreenText);
+        });
+}
+
+// the menu and settings hide the boards behind them
+fn spawn_dimmed_screen(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    mut selected: ResMut<Selected>,
+) {
+    selected.0 = 0;
+    spawn_screen(
+        &mut commands,
+        &asset_server,
+        Color::rgba(0., 0., 0., 0.85),
+        AlignItems::Center,
+        "",
+    );
+}
+
+fn spawn_pause_screen(mut commands: Commands, asset_server: Res<AssetServer>) {
+    spawn_screen(
+        &mut commands,
+        &asset_server,
+        Color::rgba(0., 0., 0., 0.6),
+        AlignItems::Center,
+        "PAUSED\n\nESC  carry on\nQ    quit to the menu",
+    );
+}
+
+// along the bottom, so the finish screens on the boards can still be read
+fn spawn_results_screen(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    remote: Query<(), With<Remote>>,
+) {
+    // online, starting again is up to the two ends agreeing, which they don't do yet
+    let value = if remote.is_empty() {
+        "R    play again\nESC  menu"
+    } else {
+        "GAME OVER"
+    };
+    spawn_screen(
+        &mut commands,
+        &asset_server,
+        Color::NONE,
+        AlignItems::FlexStart,
+        value,
+    );
+}
+
+fn despawn_screen(mut commands: Commands, screens: Query<Entity, With<Screen>>) {
+    for ent in screens.iter() {
+        commands.entity(ent).despawn_recursive();
+    }
+}
+
+fn set_text(texts: &mut Query<&mut Text, With<ScreenText>>, value: String) {
+    for mut text in texts.iter_mut() {
+        // only when it's changed, so the text isn't laid out again every frame
+        if text.sections[0].value != value {
+            text.sections[0].value = value.clone();
+        }
+    }
+}
+
+// the lines of a screen, with a marker by the one that's picked
+fn list_text(title: &str, lines: impl Iterator<Item = String>, selected: usize) -> String {
+    let mut value = format!("{}\n\n", title);
+    for (idx, line) in lines.enumerate() {
+        let marker = if idx == selected { ">" } else { " " };
+        value.push_str(&format!("{} {}\n", marker, line));
+    }
+    value
+}
+
+fn menu_text(selected: usize) -> String {
+    let lines = menu_items().into_iter().map(|item| match item {
+        MenuItem::Play(name) => name.to_uppercase(),
+        MenuItem::Settings => "SETTINGS".to_string(),
+        MenuItem::Controls => "CONTROLS".to_string(),
+        MenuItem::Quit => "QUIT".to_string(),
+    });
+    list_text("KNOCKOFF TETRIS", lines, selected)
+}
+
+fn draw_menu(selected: Res<Selected>, mut texts: Query<&mut Text, With<ScreenText>>) {
+    set_text(&mut texts, menu_text(selected.0));
+}
+
+fn menu_input(
+    mut kb: ResMut<Input<KeyCode>>,
+    mut selected: ResMut<Selected>,
+    mut mode: ResMut<Mode>,
+    mut state: ResMut<State<GameState>>,
+    mut restart: EventWriter<Restart>,
+    mut exit: EventWriter<AppExit>,
+) {
+    let items = menu_items();
+    move_selection(&kb, &mut selected, items.len());
+    if !take_key(&mut kb, KeyCode::Return) {
+        return;
+    }
+    match items[selected.0] {
+        MenuItem::Play(name) => {
+            mode.0 = mode_by_name(name).expect("the menu only lists known modes");
+            restart.send(Restart);
+            let _ = state.set(GameState::Playing);
+        }
+        MenuItem::Settings => {
+            let _ = state.set(GameState::Settings);
+        }
+        MenuItem::Controls => {
+            let _ = state.set(GameState::Controls);
+        }
+        MenuItem::Quit => exit.send(AppExit),
+    }
+}
+
+fn settings_text(
+    selected: usize,
+    animation: &Animation,
+    delays: &Delays,
+    settings: &Settings,
+) -> String {
+    let lines = SETTINGS.iter().map(|setting| match setting {
+        Setting::Animation => {
+            let easing = match animation.easing {
+                None => "off",
+                Some(Easing::Linear) => "linear",
+                Some(Easing::EaseOut) => "ease-out",
+                Some(Easing::EaseInOut) => "ease-in-out",
+            };
+            format!("ANIMATION         < {} >", easing)
+        }
+        Setting::ClearEffect => {
+            let effect = match delays.clear_effect {
+                ClearEffect::Flash => "flash",
+                ClearEffect::Dissolve => "dissolve",
+            };
+            format!("CLEAR EFFECT      < {} >", effect)
+        }
+        Setting::LineClearDelay => {
+            format!("LINE CLEAR DELAY  < {}ms >", delays.line_clear.as_millis())
+        }
+        Setting::EntryDelay => format!("ENTRY DELAY       < {}ms >", delays.entry.as_millis()),
+        Setting::Skin => format!("SKIN              < {} >", settings.skin),
+        Setting::Ghost => format!("GHOST             < {} >", settings.ghost.name()),
+        Setting::Palette => format!("PALETTE           < {} >", settings.palette.name()),
+        Setting::Symbols => {
+            let symbols = if settings.symbols { "on" } else { "off" };
+            format!("SYMBOLS           < {} >", symbols)
+        }
+        Setting::Back => "BACK".to_string(),
+    });
+    list_text("SETTINGS", lines, selected)
+}
+
+fn draw_settings(
+    selected: Res<Selected>,
+    animation: Res<Animation>,
+    delays: Res<Delays>,
+    settings: Res<Settings>,
+    mut texts: Query<&mut Text, With<ScreenText>>,
+) {
+    set_text(
+        &mut texts,
+        settings_text(selected.0, &animation, &delays, &settings),
+    );
+}
+
+fn step_delay(delay: Duration, up: bool) -> Duration {
+    if up {
+        (delay + DELAY_STEP).min(MAX_DELAY)
+    } else {
+        delay.saturating_sub(DELAY_STEP)
+    }
+}
+
+// the choice after or before `current`, wrapping around
+fn cycle<T: Clone + PartialEq>(choices: &[T], current: &T, up: bool) -> T {
+    let idx = choices
+        .iter()
+        .position(|choice| choice == current)
+        .unwrap_or(0);
+    let len = choices.len();
+    choices[(if up { idx + 1 } else { idx + len - 1 }) % len].clone()
+}
+
+// left and right go through the choices for a setting
+fn change_setting(
+    setting: Setting,
+    up: bool,
+    animation: &mut Animation,
+    delays: &mut Delays,
+    settings: &mut Settings,
+    skins: &[String],
+) {
+    match setting {
+        Setting::Animation => animation.easing = cycle(EASINGS, &animation.easing, up),
+        Setting::ClearEffect => {
+            delays.clear_effect = match delays.clear_effect {
+                ClearEffect::Flash => ClearEffect::Dissolve,
+                ClearEffect::Dissolve => ClearEffect::Flash,
+            }
+        }
+        Setting::LineClearDelay => delays.line_clear = step_delay(delays.line_clear, up),
+        Setting::EntryDelay => delays.entry = step_delay(delays.entry, up),
+        Setting::Skin => settings.skin = cycle(skins, &settings.skin, up),
+        Setting::Ghost => settings.ghost = cycle(&GhostStyle::ALL, &settings.ghost, up),
+        Setting::Palette => settings.palette = cycle(&Palette::ALL, &settings.palette, up),
+        Setting::Symbols => settings.symbols = !settings.symbols,
+        Setting::Back => {}
+    }
+}
+
+fn settings_input(
+    mut kb: ResMut<Input<KeyCode>>,
+    mut selected: ResMut<Selected>,
+    mut animation: ResMut<Animation>,
+    mut delays: ResMut<Delays>,
+    mut settings: ResMut<Settings>,
+    skins: Res<SkinNames>,
+    mut state: ResMut<State<GameState>>,
+) {
+    move_selection(&kb, &mut selected, SETTINGS.len());
+    let setting = SETTINGS[selected.0];
+    for (key, up) in [(KeyCode::Left, false), (KeyCode::Right, true)] {
+        if kb.just_pressed(key) {
+            change_setting(
+                setting,
+                up,
+                &mut animation,
+                &mut delays,
+                &mut settings,
+                &skins.0,
+            );
+        }
+    }
+    let back = take_key(&mut kb, KeyCode::Escape)
+        || (setting == Setting::Back && take_key(&mut kb, KeyCode::Return));
+    if back {
+        let _ = state.set(GameState::Menu);
+    }
+}
+
+// the lines of the controls screen are the layout, then each action, then back
+fn controls_text(selected: usize, rebinding: &Rebinding, config: &ControlsConfig) -> String {
+    let bindings = config.layout(rebinding.layout);
+    let lines = std::iter::once(format!("LAYOUT         < {} >", rebinding.layout.name()))
+        .chain(InputAction::ALL.iter().map(|action| {
+            let value = if rebinding.waiting == Some(*action) {
+                "press a key or button...".to_string()
+            } else {
+                let names: Vec<String> = bindings
+                    .get(action)
+                    .into_iter()
+                    .flatten()
+                    .map(|binding| binding.to_string())
+                    .collect();
+                names.join(", ")
+            };
+            format!("{:<14} {}", action.label(), value)
+        }))
+        .chain(std::iter::once("BACK".to_string()));
+    let mut value = list_text("CONTROLS", lines, selected);
+    value.push_str("\nENTER  add a binding\nDELETE clear the bindings");
+    value
+}
+
+fn draw_controls(
+    selected: Res<Selected>,
+    rebinding: Res<Rebinding>,
+    settings: Res<Settings>,
+    mut texts: Query<&mut Text, With<ScreenText>>,
+) {
+    set_text(
+        &mut texts,
+        controls_text(selected.0, &rebinding, &settings.controls),
+    );
+}
+
+#[allow(clippy::too_many_arguments)]
+fn controls_input(
+    mut kb: ResMut<Input<KeyCode>>,
+    buttons: Res<Input<GamepadButton>>,
+    axes: Res<Axis<GamepadAxis>>,
+    gamepads: Res<Gamepads>,
+    mut selected: ResMut<Selected>,
+    mut rebinding: ResMut<Rebinding>,
+    mut settings: ResMut<Settings>,
+    mut state: ResMut<State<GameState>>,
+) {
+    if let Some(action) = rebinding.waiting {
+        // escape gets out of it, rather than being bound
+        if !take_key(&mut kb, KeyCode::Escape) {
+            let binding = match any_pressed(&kb, &buttons, &axes, &gamepads) {
+                Some(binding) => binding,
+                None => return,
+            };
+            let bindings = settings
+                .controls
+                .layout_mut(rebinding.layout)
+                .entry(action)
+                .or_default();
+            if !bindings.contains(&binding) {
+                bindings.push(binding);
+            }
+        }
+        rebinding.waiting = None;
+        return;
+    }
+
+    let len = InputAction::ALL.len() + 2;
+    move_selection(&kb, &mut selected, len);
+    if selected.0 == 0 {
+        let layouts = Layout::ALL.len();
+        let idx = Layout::ALL
+            .iter()
+            .position(|&layout| layout == rebinding.layout)
+            .unwrap_or(0);
+        if kb.just_pressed(KeyCode::Left) {
+            rebinding.layout = Layout::ALL[(idx + layouts - 1) % layouts];
+        }
+        if kb.just_pressed(KeyCode::Right) {
+            rebinding.layout = Layout::ALL[(idx + 1) % layouts];
+        }
+    } else if let Some(&action) = InputAction::ALL.get(selected.0 - 1) {
+        if take_key(&mut kb, KeyCode::Return) {
+            rebinding.waiting = Some(action);
+        } else if kb.just_pressed(KeyCode::Delete) || kb.just_pressed(KeyCode::Back) {
+            settings
+                .controls
+                .layout_mut(rebinding.layout)
+                .insert(action, Vec::new());
+        }
+    }
+    let back = take_key(&mut kb, KeyCode::Escape)
+        || (selected.0 == len - 1 && take_key(&mut kb, KeyCode::Return));
+    if back {
+        let _ = state.set(GameState::Menu);
+    }
+}
+
+fn save_controls(settings: Res<Settings>) {
+    if let Err(err) = settings.save() {
+        println!("couldn't save the settings: {}", err);
+    }
+}
+
+fn save_settings(animation: Res<Animation>, delays: Res<Delays>, mut settings: ResMut<Settings>) {
+    settings.remember(&animation, &delays);
+    if let Err(err) = settings.save() {
+        println!("couldn't save the settings: {}", err);
+    }
+}
+
+// online, the other end carries on regardless, so there's no pausing
+fn pause_game(
+    mut state: ResMut<State<GameState>>,
+    mut pressed: Query<&mut PressedActions>,
+    remote: Query<(), With<Remote>>,
+) {
+    if remote.is_empty() && take_action(&mut pressed, InputAction::Pause) {
+        let _ = state.set(GameState::Paused);
+    }
+}
+
+fn pause_input(
+    mut kb: ResMut<Input<KeyCode>>,
+    mut state: ResMut<State<GameState>>,
+    mut pressed: Query<&mut PressedActions>,
+) {
+    if take_action(&mut pressed, InputAction::Pause) {
+        let _ = state.set(GameState::Playing);
+    } else if take_key(&mut kb, KeyCode::Q) {
+        let _ = state.set(GameState::Menu);
+    }
+}
+
+// only checked as players finish, so a restart doesn't see the ones it's about to
+// clear and end the game again straight away
+fn detect_game_over(
+    mut state: ResMut<State<GameState>>,
+    newly_finished: Query<(), Added<Finished>>,
+    players: Query<Option<&Finished>, With<Player>>,
+) {
+    if newly_finished.is_empty() {
+        return;
+    }
+    if players.iter().all(|finished| finished.is_some()) {
+        let _ = state.set(GameState::GameOver);
+    }
+}
+
+fn results_input(
+    mut kb: ResMut<Input<KeyCode>>,
+    mut state: ResMut<State<GameState>>,
+    mut restart: EventWriter<Restart>,
+    remote: Query<(), With<Remote>>,
+) {
+    if !remote.is_empty() {
+        return;
+    }
+    if take_key(&mut kb, KeyCode::R) {
+        restart.send(Restart);
+    } else if take_key(&mut kb, KeyCode::Escape) {
+        let _ = state.set(GameState::Menu);
+    }
+}
+
+// whatever restarted the game, like the next puzzle, it's being played again
+fn play_again(mut state: ResMut<State<GameState>>, mut restart: EventReader<Restart>) {
+    if restart.iter().count() > 0 {
+        let _ = state.set(GameState::Playing);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::Duration;
+
+    use crate::tetris_block::{
+        delays::Delays, palette::Palette, settings::Settings, skin::GhostStyle,
+        tweening_position::Animation,
+    };
+
+    use super::{change_setting, menu_text, Setting, MAX_DELAY};
+
+    #[test]
+    fn test_menu_text() {
+        let text = menu_text(1);
+        assert!(text.starts_with("KNOCKOFF TETRIS\n\n  FREE\n> SPRINT\n"));
+        assert!(text.ends_with("  SETTINGS\n  CONTROLS\n  QUIT\n"));
+    }
+
+    #[test]
+    fn test_change_setting() {
+        let mut animation = Animation::default();
+        let mut delays = Delays::default();
+        let mut settings = Settings::default();
+        let skins = ["plain".to_string(), "tile".to_string()];
+        let mut change = |setting, up, animation: &mut Animation, delays: &mut Delays| {
+            change_setting(setting, up, animation, delays, &mut settings, &skins)
+        };
+
+        // round the easings and back to where it started
+        let start = animation.easing;
+        for _ in 0..4 {
+            change(Setting::Animation, true, &mut animation, &mut delays);
+        }
+        assert_eq!(animation.easing, start);
+        change(Setting::Animation, false, &mut animation, &mut delays);
+        assert_ne!(animation.easing, start);
+
+        change(Setting::EntryDelay, false, &mut animation, &mut delays);
+        assert_eq!(delays.entry, Duration::ZERO);
+        change(Setting::LineClearDelay, true, &mut animation, &mut delays);
+        assert_eq!(delays.line_clear, Duration::from_millis(300));
+        for _ in 0..100 {
+            change(Setting::LineClearDelay, true, &mut animation, &mut delays);
+        }
+        assert_eq!(delays.line_clear, MAX_DELAY);
+
+        change(Setting::Skin, true, &mut animation, &mut delays);
+        change(Setting::Ghost, false, &mut animation, &mut delays);
+        change(Setting::Palette, false, &mut animation, &mut delays);
+        change(Setting::Symbols, true, &mut animation, &mut delays);
+        assert_eq!(settings.skin, "plain");
+        assert_eq!(settings.ghost, GhostStyle::Off);
+        assert_eq!(settings.palette, Palette::HighContrast);
+        assert!(settings.symbols);
+    }
+}
@@ -1,23 +1,87 @@
+mod ai;
+mod bit_board;
 mod block_definition;
 mod board;
 mod cell_positioning;
+mod controls;
+mod delays;
+mod env;
+mod fumen;
+mod garbage;
+mod hud;
+mod line_clear;
+mod menu;
+mod mode;
 mod movable_block;
+mod move_gen;
+mod net;
+mod palette;
+mod piece_queue;
+mod puzzle;
+mod save;
+mod settings;
 mod skate_timer;
+mod skin;
+mod spectate;
+mod tbp;
 mod tuple_util;
-// mod tweening_position;
+mod tweening_position;
 
-use self::board::Board;
+use self::ai::AiPlugin;
+pub use self::bit_board::BitBoard;
+pub use self::board::{Board, Cell, Fill};
 use self::cell_positioning::{AbsolutePositionedCell, CellPositioningPlugin};
-use self::movable_block::{BlockName, MovableBlock, RotDir};
+use self::controls::{AutoRepeat, Controls, ControlsPlugin, PressedActions};
+pub use self::controls::{Binding, Bindings, ControlsConfig, InputAction, Layout};
+use self::delays::{waiting, DelaysPlugin, EntryDelay, LineClearDelay};
+pub use self::delays::{ClearEffect, Delays};
+pub use self::env::{
+    Action, ActionSpace, Env, EnvConfig, Observation, PieceState, INVALID_PLACE_REWARD,
+    STANDARD_BLOCKS,
+};
+pub use self::fumen::{decode as decode_fumen, encode as encode_fumen, Field, FumenPlugin, Page};
+use self::garbage::GarbagePlugin;
+pub use self::garbage::{AttackTable, GarbageQueue, OutgoingAttack};
+use self::hud::HudPlugin;
+use self::line_clear::detect_spin;
+pub use self::line_clear::{LineClear, Spin, Streaks};
+pub use self::menu::GameState;
+use self::menu::MenuPlugin;
+pub use self::mode::{
+    format_time, mode_by_name, Cheese, Dig, FreePlay, GameMode, Marathon, Mode, Outcome, Progress,
+    Ranking, Restart, Sprint, TopOut, Ultra, Zen, MODE_NAMES,
+};
+use self::mode::{Finished, ModePlugin};
+pub use self::movable_block::{BlockName, MovableBlock};
+use self::move_gen::apply_move;
+pub use self::move_gen::{reachable_placements, Collision, Move, Placement};
+pub use self::net::{NetPlugin, Session};
+pub use self::palette::Palette;
+use self::piece_queue::PieceQueue;
+pub use self::piece_queue::Randomizer;
+pub use self::puzzle::{load_puzzles, Goal, Puzzle, PuzzlePlugin};
+pub use self::save::{SavePlugin, SavedGame};
+pub use self::settings::Settings;
 use self::skate_timer::SkateTimer;
+pub use self::skin::GhostStyle;
+use self::skin::{Skin, SkinPlugin};
+pub use self::spectate::SpectatePlugin;
+pub use self::tbp::TbpPlugin;
+use self::tweening_position::TweeningPositionPlugin;
+pub use self::tweening_position::{Animation, Easing};
 use crate::tetris_block::cell_positioning::{AbsolutePositionedPiece, RelativePositionedCell};
-use crate::{CELL_SIDE_LEN, GRID_CELLS};
-use bevy::{core::FixedTimestep, ecs::schedule::ShouldRun, prelude::*};
+use crate::{BOARD_GAP, CELL_SIDE_LEN, HUD_WIDTH};
+use bevy::{ecs::schedule::ShouldRun, prelude::*};
 use rand::{thread_rng, Rng};
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
 
 #[derive(Component)]
 struct TetrisBlock {
     movable: MovableBlock,
+    color: Color,
+    // the last move that succeeded, to tell if the block was spun into place
+    last_move: Option<Move>,
 }
 
 // Marks the active TetrisBlock (which is being moved by the player)
@@ -29,25 +93,167 @@ struct Active;
 #[derive(Component)]
 struct Ghost;
 
+// one board and everything that goes with it. the active block, its ghost and the
+// cells placed on the board are all children of the player, so its transform places
+// the board on the screen.
+#[derive(Component)]
+struct Player {
+    index: usize,
+}
+
+// marks the player on the other end of an online game
+#[derive(Component)]
+struct Remote;
+
 #[derive(Deref)]
 struct FrameNum(u64);
 
+#[derive(Component)]
 struct Paused(bool);
 
-pub struct TetrisBlockPlugin;
+#[derive(Component)]
+struct Gravity(Timer);
+
+// an input for the active block, sent by the keyboard or the ai
+#[derive(Copy, Clone, Debug, PartialEq, Serialize, Deserialize)]
+enum BlockAction {
+    Move(Move),
+    HardDrop,
+    Hold,
+}
+
+struct PlayerAction {
+    player: Entity,
+    action: BlockAction,
+}
+
+// the actions to apply this step. when playing locally that's every PlayerAction as
+// it's made, online it's both players' inputs for the next tick once they're in.
+struct StepAction {
+    player: Entity,
+    action: BlockAction,
+}
+
+// whether the game moves forward this frame, and by how much time. online, the game
+// only moves when both sides' inputs are in, a fixed amount at a time, so both sides
+// see the same game.
+struct Step {
+    run: bool,
+    delta: Duration,
+}
+
+// the block put aside by the player, which can be swapped with the active block
+// once per placed block
+#[derive(Component, Default, Clone, Serialize, Deserialize)]
+struct Hold {
+    piece: Option<BlockName>,
+    used: bool,
+    // the mode doesn't allow holding
+    disabled: bool,
+    // taken out of hold, to be spawned instead of the next block from the queue
+    swap_in: Option<BlockName>,
+}
+
+// sent once a block has been locked and any lines it filled are cleared
+struct BlockPlaced {
+    player: Entity,
+    clear: LineClear,
+}
+
+// the stack has reached the top, the next block can't come in. what happens then
+// is up to the mode.
+struct ToppedOut {
+    player: Entity,
+}
+
+pub struct TetrisBlockPlugin {
+    // 1 for single player, 2 for split screen versus
+    pub players: usize,
+    // picks the pieces and garbage holes, random if not given
+    pub seed: Option<u64>,
+    // online, the index of the player on the other end of the connection. their
+    // board only moves with the inputs sent from there.
+    pub remote_player: Option<usize>,
+    // the screen the game starts on, the main menu unless it's been set up already
+    pub start: GameState,
+}
+impl Default for TetrisBlockPlugin {
+    fn default() -> Self {
+        TetrisBlockPlugin {
+            players: 1,
+            seed: None,
+            remote_player: None,
+            start: GameState::Menu,
+        }
+    }
+}
 impl Plugin for TetrisBlockPlugin {
     fn build(&self, app: &mut bevy::prelude::App) {
-        app.insert_resource(Board::new(
-            GRID_CELLS.width as usize,
-            GRID_CELLS.height as usize,
-        ));
-        app.insert_resource(Paused(true));
-        app.insert_resource(FrameNum(0));
-        app.insert_resource(PlaceBlock(false));
-        app.add_system(update_pause_state);
-        // app.add_plugin(TweeningPositionPlugin);
-        app.add_plugin(CellPositioningPlugin);
+        // insert loaded settings before adding the plugin to use them
+        app.init_resource::<Settings>();
+        let settings = app.world.resource::<Settings>().clone();
+
+        // every player gets the same pieces
+        let seed = self.seed.unwrap_or_else(|| thread_rng().gen());
+        let online = self.remote_player.is_some();
+        for index in 0..self.players {
+            // online, the local player gets the whole keyboard. each player has a
+            // gamepad of their own.
+            let layout = match (self.players, index) {
+                _ if online => Layout::Single,
+                (1, _) => Layout::Single,
+                (_, 0) => Layout::LeftSide,
+                _ => Layout::RightSide,
+            };
+            let controls = Controls {
+                layout,
+                gamepad: if online { 0 } else { index },
+            };
+            let mut player = app.world.spawn();
+            if self.remote_player == Some(index) {
+                player.insert(Remote);
+            } else {
+                player
+                    .insert(controls)
+                    .insert(PressedActions::default())
+                    .insert(AutoRepeat::default());
+            }
+            player
+                .insert(Player { index })
+                .insert(Board::new(settings.board_width, settings.board_height))
+                .insert(PieceQueue::new(seed, &settings.pieces, settings.randomizer))
+                .insert(Hold::default())
+                .insert(Streaks::default())
+                .insert(Progress::default())
+                .insert(GarbageQueue::new(seed))
+                .insert(Paused(false))
+                .insert(Gravity(Timer::from_seconds(
+                    1.5 * settings.gravity_scale,
+                    true,
+                )))
+                .insert(PlaceBlock(false))
+                .insert_bundle(TransformBundle::from_transform(Transform::from_xyz(
+                    board_x(index, self.players, settings.board_width),
+                    0.,
+                    0.,
+                )));
+        }
 
+        app.insert_resource(FrameNum(0));
+        app.insert_resource(Step {
+            run: true,
+            delta: Duration::ZERO,
+        });
+        app.add_event::<PlayerAction>();
+        app.add_event::<StepAction>();
+        app.add_event::<BlockPlaced>();
+        app.add_event::<ToppedOut>();
+        app.add_plugin(ControlsPlugin);
+        app.add_system(read_player_actions);
+        if !online {
+            app.add_system(update_pause_state);
+            app.add_system(relay_actions.after(read_player_actions));
+        }
         {
             let mut stage = SystemStage::parallel();
             stage.add_system(inc_frame_num);
@@ -56,28 +262,22 @@ impl Plugin for TetrisBlockPlugin {
 
         // step 1 - add new blocks to the game state
         {
-            let mut spawn_new_blocks = SystemStage::parallel();
-            spawn_new_blocks.add_system_set(
-                SystemSet::new()
-                    .with_run_criteria(no_active_block_exists)
-                    .with_system(spawn_new_block),
-            );
+            let mut spawn_new_blocks = SystemStage::parallel().with_run_criteria(step_ready);
+            spawn_new_blocks.add_system(spawn_new_block);
             app.add_stage_after(CoreStage::Update, "spawn_new_blocks", spawn_new_blocks);
         }
 
         // step 2 - calculate the new position of blocks, finalize block placement,
         // clear any filled lines
         {
-            let mut update_block_positions_stage = SystemStage::parallel();
+            let mut update_block_positions_stage =
+                SystemStage::parallel().with_run_criteria(step_ready);
             update_block_positions_stage
-                .add_system(handle_block_user_movement)
-                .add_system(position_ghost_block.after(handle_block_user_movement))
-                // moves the active block down every 1 second
-                .add_system_set(
-                    SystemSet::new()
-                        .with_run_criteria(FixedTimestep::step(1.5))
-                        .with_system(move_active_block_down.after(handle_block_user_movement)),
-                )
+                .add_system(handle_block_actions)
+                .add_system(print_debug_state)
+                .add_system(position_ghost_block.after(handle_block_actions))
+                // moves the active block down every 1.5 seconds
+                .add_system(move_active_block_down.after(handle_block_actions))
                 // checks if the skate timer can be started after block movement
                 .add_system(check_skate_timer.after(move_active_block_down))
                 .add_system(place_block.after(check_skate_timer));
@@ -89,80 +289,163 @@ impl Plugin for TetrisBlockPlugin {
             );
         }
 
+        app.add_plugin(MenuPlugin { start: self.start });
+        app.add_plugin(DelaysPlugin);
+        app.add_plugin(SkinPlugin);
+        app.add_plugin(HudPlugin);
+        app.add_plugin(AiPlugin);
+        app.add_plugin(ModePlugin);
+        app.add_plugin(GarbagePlugin {
+            debug_keys: !online,
+        });
+
         // step 3 - update the Transform of all the sprites that are on the screen
         {
-            // let mut update_block_transforms = SystemStage::parallel();
-            // app.add_stage_after(
-            //     "update_block_positions",
-            //     "update_block_transforms",
-            //     update_block_transforms,
-            // );
+            let update_block_transforms = SystemStage::parallel();
+            app.add_stage_after(
+                "update_block_positions",
+                "update_block_transforms",
+                update_block_transforms,
+            );
         }
+        app.add_plugin(TweeningPositionPlugin);
+        app.add_plugin(CellPositioningPlugin);
     }
 }
 
-fn inc_frame_num(mut frame_num: ResMut<FrameNum>) {
-    frame_num.0 += 1;
+// how far from the middle of the screen a player's board is, with its hud to the right
+fn board_x(index: usize, players: usize, board_width: usize) -> f32 {
+    let spacing = board_width as f32 * CELL_SIDE_LEN + HUD_WIDTH + BOARD_GAP;
+    (index as f32 - (players - 1) as f32 / 2.) * spacing - HUD_WIDTH / 2.
+}
+
+// the player on the right, who the ai and bots play as. online, that's whichever
+// side is played from here.
+fn last_player(world: &mut World) -> Option<Entity> {
+    world
+        .query_filtered::<(Entity, &Player), Without<Remote>>()
+        .iter(world)
+        .max_by_key(|(_, player)| player.index)
+        .map(|(ent, _)| ent)
 }
 
-fn update_pause_state(input: Res<Input<KeyCode>>, mut paused: ResMut<Paused>) {
-    if input.just_pressed(KeyCode::Space) {
-        paused.0 = !paused.0;
+// the game only steps while it's being played, not behind a menu or paused
+fn step_ready(step: Res<Step>, state: Res<State<GameState>>) -> ShouldRun {
+    if step.run && *state.current() == GameState::Playing {
+        ShouldRun::Yes
+    } else {
+        ShouldRun::No
     }
 }
 
-fn no_active_block_exists(query: Query<(), With<TetrisBlock>>) -> ShouldRun {
-    if query.iter().next().is_some() {
-        return ShouldRun::No;
+// playing locally, actions are applied as soon as they're made
+fn relay_actions(
+    time: Res<Time>,
+    mut step: ResMut<Step>,
+    mut actions: EventReader<PlayerAction>,
+    mut step_actions: EventWriter<StepAction>,
+) {
+    step.delta = time.delta();
+    for &PlayerAction { player, action } in actions.iter() {
+        step_actions.send(StepAction { player, action });
     }
-    ShouldRun::Yes
 }
 
-const COLORS: &[Color] = &[
-    Color::RED,
-    Color::GREEN,
-    Color::BLUE,
-    Color::ORANGE,
-    Color::PURPLE,
-];
-fn rand_color() -> Color {
-    COLORS[thread_rng().gen_range(0..COLORS.len())]
+fn inc_frame_num(mut frame_num: ResMut<FrameNum>) {
+    frame_num.0 += 1;
 }
 
-const BLOCKS: &[BlockName] = &[
-    // BlockName::L,
-    // BlockName::J,
-    // BlockName::O,
-    BlockName::I,
-    // BlockName::T,
-    // BlockName::S,
-    // BlockName::Z,
-];
-fn rand_block() -> BlockName {
-    BLOCKS[thread_rng().gen_range(0..BLOCKS.len())]
+fn update_pause_state(pressed: Query<&PressedActions>, mut query: Query<&mut Paused>) {
+    // once however many players pressed it, so split screen players don't undo it
+    if pressed
+        .iter()
+        .any(|pressed| pressed.contains(InputAction::ToggleGravity))
+    {
+        for mut paused in query.iter_mut() {
+            paused.0 = !paused.0;
+        }
+    }
+}
+
+fn rand_color(colors: &[Color]) -> Color {
+    colors[thread_rng().gen_range(0..colors.len())]
 }
 
-fn spawn_new_block(mut commands: Commands, frame_num: Res<FrameNum>) {
-    let color = rand_color();
-    let block = rand_block();
+#[allow(clippy::type_complexity)]
+fn spawn_new_block(
+    mut commands: Commands,
+    frame_num: Res<FrameNum>,
+    settings: Res<Settings>,
+    skin: Res<Skin>,
+    mut players: Query<
+        (
+            Entity,
+            &Board,
+            &mut PieceQueue,
+            &mut Hold,
+            Option<&LineClearDelay>,
+            Option<&EntryDelay>,
+        ),
+        Without<Finished>,
+    >,
+    block_query: Query<&Parent, With<TetrisBlock>>,
+    mut topped_out: EventWriter<ToppedOut>,
+) {
+    for (player, board_state, mut piece_queue, mut hold, clearing, entry) in players.iter_mut() {
+        if block_query.iter().any(|parent| parent.0 == player) || waiting(clearing, entry) {
+            continue;
+        }
 
-    println!("{} - spawning new block: {:?}", frame_num.0, block);
+        // the next block is only taken once there's room for it. a fixed sequence of
+        // pieces can run out, then whatever is left in hold comes out.
+        let from_queue = piece_queue.upcoming().next();
+        let block = match hold.swap_in.or(from_queue).or(hold.piece) {
+            Some(block) => block,
+            None => continue,
+        };
+        let spawn_at = board_state.spawn_position();
+        let movable = block.create_movable(spawn_at);
+        if !board_state.can_place(&movable) {
+            println!("{} - no room to spawn {:?}", frame_num.0, block);
+            topped_out.send(ToppedOut { player });
+            continue;
+        }
+        if hold.swap_in.take().is_none() {
+            if from_queue.is_some() {
+                piece_queue.pop();
+            } else {
+                hold.piece = None;
+            }
+        }
 
-    let spawn_at = IVec2::new(
-        (GRID_CELLS.width / 2) as i32,
-        (GRID_CELLS.height - 3) as i32,
-    );
-    let movable = block.create_movable(spawn_at);
+        println!("{} - spawning new block: {:?}", frame_num.0, block);
+        spawn_block(
+            &mut commands,
+            &skin,
+            player,
+            movable,
+            rand_color(&settings.colors()),
+        );
+    }
+}
 
+// the active block, and its ghost showing where it would land
+fn spawn_block(
+    commands: &mut Commands,
+    skin: &Skin,
+    player: Entity,
+    movable: MovableBlock,
+    color: Color,
+) {
     // the active tetris block
-    commands
+    let active = commands
         .spawn()
         .insert_bundle(TransformBundle::identity())
         // xxx - consider removing MovableBlock entirely as it contains
         // basically the same state as AbsolutePositionedPiece
         .insert(AbsolutePositionedPiece {
-            pos: spawn_at,
-            rot: 0,
+            pos: movable.root_position(),
+            rot: movable.rot(),
             def: movable.definition,
         })
         .insert_bundle(SpriteBundle {
@@ -177,22 +460,33 @@ fn spawn_new_block(mut commands: Commands, frame_num: Res<FrameNum>) {
             },
             ..default()
         })
-        .with_children(|builder| add_cell_children(builder, color, false, &movable))
+        .with_children(|builder| {
+            add_cell_children(builder, skin, color, CellStyle::Solid, &movable)
+        })
         .insert(TetrisBlock {
             movable: movable.clone(),
-        });
+            color,
+            last_move: None,
+        })
+        .id();
 
     // the ghost tetris block
-    commands
+    let ghost = commands
         .spawn()
         .insert_bundle(TransformBundle::identity())
-        .with_children(|builder| add_cell_children(builder, color, true, &movable))
+        .with_children(|builder| {
+            add_cell_children(builder, skin, color, CellStyle::Ghost, &movable)
+        })
         .insert(AbsolutePositionedPiece {
-            pos: spawn_at,
-            rot: 0,
+            pos: movable.root_position(),
+            rot: movable.rot(),
             def: movable.definition,
         })
-        .insert(TetrisBlock { movable })
+        .insert(TetrisBlock {
+            movable,
+            color,
+            last_move: None,
+        })
         .insert_bundle(SpriteBundle {
             sprite: Sprite {
                 color: Color::BLACK,
@@ -205,39 +499,28 @@ fn spawn_new_block(mut commands: Commands, frame_num: Res<FrameNum>) {
             },
             ..default()
         })
-        .insert(Ghost);
+        .insert(Ghost)
+        .id();
+
+    commands.entity(player).push_children(&[active, ghost]);
+}
+
+#[derive(Copy, Clone, PartialEq, Eq)]
+enum CellStyle {
+    Solid,
+    Ghost,
+    // the placement suggested by the ai
+    Hint,
 }
 
 fn add_cell_children(
     builder: &mut ChildBuilder,
+    skin: &Skin,
     color: Color,
-    is_ghost: bool,
+    style: CellStyle,
     movable: &MovableBlock,
 ) {
-    let big_sprite = || Sprite {
-        color,
-        custom_size: Some(Vec2::new(CELL_SIDE_LEN, CELL_SIDE_LEN)),
-        ..default()
-    };
-    let little_sprite = || Sprite {
-        color: color * 0.5,
-        custom_size: Some(Vec2::new(CELL_SIDE_LEN * 0.9, CELL_SIDE_LEN * 0.9)),
-        ..default()
-    };
-    let ghost_sprite = ||
-        // desaturated color
-        Sprite {
-            color: color.as_hsla() * Vec4::new(1., 0.2, 1.0, 1.0),
-            custom_size: Some(Vec2::new(CELL_SIDE_LEN, CELL_SIDE_LEN)),
-            ..default()
-        };
-    fn at_z_level(z: f32) -> Transform {
-        Transform {
-            translation: Vec3::new(0., 0., z),
-            ..default()
-        }
-    }
-
+    let fill = Fill::Piece(movable.name());
     for pos in movable.relative_positions() {
         builder
             .spawn()
@@ -246,250 +529,374 @@ fn add_cell_children(
                 pos,
                 def: movable.definition,
             })
-            .with_children(|p2| {
-                if !is_ghost {
-                    p2.spawn().insert_bundle(SpriteBundle {
-                        sprite: big_sprite(),
-                        transform: at_z_level(10.),
-                        ..default()
-                    });
-                    p2.spawn().insert_bundle(SpriteBundle {
-                        sprite: little_sprite(),
-                        transform: at_z_level(11.),
-                        ..default()
-                    });
-                } else {
-                    p2.spawn()
-                        .insert_bundle(TransformBundle::identity())
-                        // this position will be updated later to move the block to the lowest point possible on the screen
-                        .insert_bundle(SpriteBundle {
-                            sprite: ghost_sprite(),
-                            transform: at_z_level(9.),
-                            ..default()
-                        });
-                }
-            });
+            .with_children(|p2| add_cell_sprites(p2, skin, fill, color, style));
     }
 }
 
-fn handle_block_user_movement(
-    kb: Res<Input<KeyCode>>,
-    board_state: Res<Board>,
-    mut place_block: ResMut<PlaceBlock>,
-    mut active_block_query: Query<(&mut TetrisBlock, &mut AbsolutePositionedPiece), Without<Ghost>>,
-) {
-    let (mut block, mut app) = match active_block_query.get_single_mut() {
-        Ok(ok) => ok,
-        _ => return,
-    };
-
-    let mut nudge_movable = |dir| {
-        let movable = block.movable.move_relative(dir);
-        println!(
-            "attempting to nudge in {} to {}",
-            dir,
-            movable.root_position()
-        );
-
-        if board_state.can_place(&movable) {
-            block.movable = movable;
-            true
-        } else {
-            false
-        }
-    };
+// a cell on the board which didn't come from a placed block
+fn spawn_cell(
+    commands: &mut Commands,
+    skin: &Skin,
+    pos: IVec2,
+    fill: Fill,
+    color: Color,
+) -> Entity {
+    commands
+        .spawn()
+        .insert_bundle(TransformBundle::identity())
+        .insert(AbsolutePositionedCell { pos, rot: 0 })
+        .with_children(|builder| add_cell_sprites(builder, skin, fill, color, CellStyle::Solid))
+        .id()
+}
 
-    if kb.just_pressed(KeyCode::Left) {
-        nudge_movable((-1, 0).into());
-    }
-    if kb.just_pressed(KeyCode::Right) {
-        nudge_movable((1, 0).into());
+// the sprites drawn for a single cell
+fn add_cell_sprites(
+    p2: &mut ChildBuilder,
+    skin: &Skin,
+    fill: Fill,
+    color: Color,
+    style: CellStyle,
+) {
+    let color = skin.tint(fill, color);
+    match style {
+        CellStyle::Solid => skin.spawn_cell(p2, fill, color, 10.),
+        // this position will be updated later to move the block to the lowest point possible on the screen
+        CellStyle::Ghost => skin.spawn_ghost_cell(p2, fill, color, 9.),
+        CellStyle::Hint => skin.spawn_tile(
+            p2,
+            fill,
+            color * Vec4::new(1., 1., 1., 0.35),
+            CELL_SIDE_LEN * 0.8,
+            Vec3::new(0., 0., 12.),
+        ),
     }
-    // soft drop
-    if kb.just_pressed(KeyCode::Down) {
-        while nudge_movable((0, -1).into()) {}
+}
+
+fn read_player_actions(
+    state: Res<State<GameState>>,
+    players: Query<(Entity, &PressedActions)>,
+    mut actions: EventWriter<PlayerAction>,
+) {
+    // the menus use the arrow keys too, which shouldn't be waiting when the game starts
+    if *state.current() != GameState::Playing {
+        return;
     }
-    // hard drop
-    if kb.just_pressed(KeyCode::Up) {
-        println!("hard drop");
-        while nudge_movable((0, -1).into()) {}
-        println!("block is at {} now", block.movable.root_position());
-        place_block.0 = true;
+    for (player, pressed) in players.iter() {
+        for &input in pressed.0.iter() {
+            let action = match input {
+                InputAction::MoveLeft => BlockAction::Move(Move::Left),
+                InputAction::MoveRight => BlockAction::Move(Move::Right),
+                InputAction::SoftDrop => BlockAction::Move(Move::SoftDrop),
+                InputAction::HardDrop => BlockAction::HardDrop,
+                InputAction::RotateLeft => BlockAction::Move(Move::RotateLeft),
+                InputAction::RotateRight => BlockAction::Move(Move::RotateRight),
+                InputAction::Hold => BlockAction::Hold,
+                InputAction::Pause | InputAction::ToggleGravity => continue,
+            };
+            actions.send(PlayerAction { player, action });
+        }
     }
+}
+
+fn handle_block_actions(
+    mut commands: Commands,
+    mut actions: EventReader<StepAction>,
+    mut players: Query<(&Board, &mut PlaceBlock, &mut Hold)>,
+    mut active_block_query: Query<
+        (
+            Entity,
+            &Parent,
+            &mut TetrisBlock,
+            &mut AbsolutePositionedPiece,
+        ),
+        Without<Ghost>,
+    >,
+    ghost_query: Query<(Entity, &Parent), With<Ghost>>,
+) {
+    // players whose block was swapped out, the rest of their actions are dropped
+    let mut held = Vec::new();
 
-    let mut rotate = |dir| {
-        let (movable, kicks) = block.movable.rotate(dir);
+    for &StepAction { player, action } in actions.iter() {
+        if held.contains(&player) {
+            continue;
+        }
+        let (board_state, mut place_block, mut hold) = match players.get_mut(player) {
+            Ok(ok) => ok,
+            _ => continue,
+        };
+        let (active_entity, _, mut block, mut app) = match active_block_query
+            .iter_mut()
+            .find(|(_, parent, _, _)| parent.0 == player)
+        {
+            Some(ok) => ok,
+            _ => continue,
+        };
 
-        for &kick in kicks {
-            let movable = movable.move_relative(kick);
-            if board_state.can_place(&movable) {
-                block.movable = movable;
-                return;
+        let mv = match action {
+            BlockAction::Move(mv) => mv,
+            BlockAction::HardDrop => {
+                println!("hard drop");
+                place_block.0 = true;
+                Move::SoftDrop
             }
+            BlockAction::Hold => {
+                if hold.used || hold.disabled {
+                    continue;
+                }
+                println!("holding {:?}", block.movable.name());
+                hold.swap_in = hold.piece.replace(block.movable.name());
+                hold.used = true;
+
+                // a new block is spawned in place of the active one, from hold or the queue
+                commands.entity(active_entity).despawn_recursive();
+                for (ghost_entity, parent) in ghost_query.iter() {
+                    if parent.0 == player {
+                        commands.entity(ghost_entity).despawn_recursive();
+                    }
+                }
+                commands.entity(player).remove::<SkateTimer>();
+                place_block.0 = false;
+                held.push(player);
+                continue;
+            }
+        };
+
+        if let Some(movable) = apply_move(board_state, &block.movable, mv) {
+            println!("{:?} moved block to {}", mv, movable.root_position());
+            block.movable = movable;
+            block.last_move = Some(mv);
         }
-    };
 
-    if kb.just_pressed(KeyCode::A) {
-        rotate(RotDir::Left);
-    }
-    if kb.just_pressed(KeyCode::D) {
-        rotate(RotDir::Right);
+        app.pos = block.movable.root_position();
+        app.rot = block.movable.rot();
     }
+}
 
-    if kb.just_pressed(KeyCode::C) {
-        println!("{:?}", board_state.as_ref());
+fn print_debug_state(
+    kb: Res<Input<KeyCode>>,
+    players: Query<(Entity, &Player, &Board)>,
+    active_block_query: Query<(&Parent, &TetrisBlock), Without<Ghost>>,
+) {
+    if !kb.just_pressed(KeyCode::C) {
+        return;
     }
 
-    app.pos = block.movable.root_position();
-    app.rot = block.movable.rot();
+    for (player, Player { index }, board_state) in players.iter() {
+        println!("player {}: {:?}", index, board_state);
+        for (parent, block) in active_block_query.iter() {
+            if parent.0 != player {
+                continue;
+            }
+            for placement in reachable_placements(board_state, &block.movable) {
+                println!(
+                    "can lock at {} (rot {}) via {:?}",
+                    placement.block.root_position(),
+                    placement.block.rotation_state(),
+                    placement.moves
+                );
+            }
+        }
+    }
 }
 
 fn position_ghost_block(
-    mut ghost_query: Query<(&mut TetrisBlock, &mut AbsolutePositionedPiece), With<Ghost>>,
-    block_query: Query<&TetrisBlock, Without<Ghost>>,
-    board_state: Res<Board>,
+    mut ghost_query: Query<(&Parent, &mut TetrisBlock, &mut AbsolutePositionedPiece), With<Ghost>>,
+    block_query: Query<(&Parent, &TetrisBlock), Without<Ghost>>,
+    players: Query<&Board>,
 ) {
-    let active = block_query.single();
-    let (mut ghost, mut app) = ghost_query.single_mut();
-
-    // from the original active position, move ghost down until it can't be moved
-    // further
-    ghost.movable = active.movable.clone();
-    while board_state.can_place(&ghost.movable.move_relative((0, -1).into())) {
-        ghost.movable = ghost.movable.move_relative((0, -1).into());
+    for (parent, mut ghost, mut app) in ghost_query.iter_mut() {
+        let active = match block_query.iter().find(|(p, _)| p.0 == parent.0) {
+            Some((_, active)) => active,
+            None => continue,
+        };
+        let board_state = match players.get(parent.0) {
+            Ok(board_state) => board_state,
+            Err(_) => continue,
+        };
+
+        // from the original active position, move ghost down until it can't be moved
+        // further
+        ghost.movable = active.movable.clone();
+        while board_state.can_place(&ghost.movable.move_relative((0, -1).into())) {
+            ghost.movable = ghost.movable.move_relative((0, -1).into());
+        }
+        app.pos = ghost.movable.root_position();
+        app.rot = ghost.movable.rot();
     }
-    app.pos = ghost.movable.root_position();
-    app.rot = ghost.movable.rot();
 }
 
 fn move_active_block_down(
-    paused: Res<Paused>,
-    board_state: Res<Board>,
-    mut query: Query<&mut TetrisBlock>,
+    step: Res<Step>,
+    mut players: Query<(Entity, &Board, &Paused, &mut Gravity)>,
+    mut query: Query<(&Parent, &mut TetrisBlock)>,
 ) {
-    if paused.0 {
-        return;
+    // tick each player's timer once, however many blocks they have
+    let mut falling = Vec::new();
+    for (player, _, paused, mut gravity) in players.iter_mut() {
+        if !paused.0 && gravity.0.tick(step.delta).just_finished() {
+            falling.push(player);
+        }
     }
 
-    for mut block in query.iter_mut() {
+    for (parent, mut block) in query.iter_mut() {
+        if !falling.contains(&parent.0) {
+            continue;
+        }
+        let (_, board_state, _, _) = match players.get(parent.0) {
+            Ok(ok) => ok,
+            Err(_) => continue,
+        };
+
         let movable = block.movable.move_relative((0, -1).into());
         if board_state.can_place(&movable) {
             block.movable = movable;
+            block.last_move = Some(Move::SoftDrop);
         }
     }
 }
 
 // should the block be placed?
+#[derive(Component)]
 struct PlaceBlock(bool);
 
 // stops the skate timer if the block can move downwards
 fn check_skate_timer(
     mut commands: Commands,
-    mut place_block: ResMut<PlaceBlock>,
     frame_num: Res<FrameNum>,
-    timer: Option<ResMut<SkateTimer>>,
-    board_state: Res<Board>,
-    time: Res<Time>,
-    query: Query<&TetrisBlock, Without<Ghost>>,
+    step: Res<Step>,
+    settings: Res<Settings>,
+    mut players: Query<(&Board, &mut PlaceBlock, Option<&mut SkateTimer>)>,
+    query: Query<(&Parent, &TetrisBlock), Without<Ghost>>,
 ) {
-    let active_movable = match query.get_single() {
-        Ok(block) => &block.movable,
-        Err(_) => return,
-    };
-
-    if board_state.can_place(&active_movable.move_relative((0, -1).into())) {
-        // if the block can move down, stop the skate timer
-        // if so, stop the skate timer
-        if timer.is_some() {
-            println!("{} - block can drop, stopping skate timer", frame_num.0);
-            commands.remove_resource::<SkateTimer>();
+    for (parent, block) in query.iter() {
+        let player = parent.0;
+        let (board_state, mut place_block, timer) = match players.get_mut(player) {
+            Ok(ok) => ok,
+            Err(_) => continue,
+        };
+        let active_movable = &block.movable;
+
+        if board_state.can_place(&active_movable.move_relative((0, -1).into())) {
+            // if the block can move down, stop the skate timer
+            // if so, stop the skate timer
+            if timer.is_some() {
+                println!("{} - block can drop, stopping skate timer", frame_num.0);
+                commands.entity(player).remove::<SkateTimer>();
+            }
+            continue;
         }
-        return;
-    }
 
-    // the block can't move down, start the timer or place the block
-    match timer {
-        Some(mut timer) => {
-            if timer.0.tick(time.delta()).just_finished() {
-                println!("{} - timer fired, signaling placing block", frame_num.0);
-                commands.remove_resource::<SkateTimer>();
-                place_block.0 = true;
+        // the block can't move down, start the timer or place the block
+        match timer {
+            Some(mut timer) => {
+                if timer.0.tick(step.delta).just_finished() {
+                    println!("{} - timer fired, signaling placing block", frame_num.0);
+                    commands.entity(player).remove::<SkateTimer>();
+                    place_block.0 = true;
+                }
+            }
+            None => {
+                println!("{} - starting skate timer", frame_num.0);
+                commands
+                    .entity(player)
+                    .insert(SkateTimer(Timer::new(settings.lock_delay(), false)));
             }
-        }
-        None => {
-            println!("{} - starting skate timer", frame_num.0);
-            commands.insert_resource(SkateTimer(Timer::from_seconds(2., false)));
         }
     }
 }
 
 fn place_block(
-    mut place_block: ResMut<PlaceBlock>,
     frame_num: Res<FrameNum>,
     mut commands: Commands,
-    active_query: Query<(Entity, &TetrisBlock, &Children), Without<Ghost>>,
-    ghost_query: Query<Entity, (With<TetrisBlock>, With<Ghost>)>,
-    mut cell_query: Query<&mut AbsolutePositionedCell>,
-    mut board_state: ResMut<Board>,
+    mut players: Query<(&mut PlaceBlock, &mut Board, &mut Hold, &mut Streaks)>,
+    active_query: Query<(Entity, &Parent, &TetrisBlock, &Children), Without<Ghost>>,
+    ghost_query: Query<(Entity, &Parent), With<Ghost>>,
+    delays: Res<Delays>,
+    mut placed: EventWriter<BlockPlaced>,
 ) {
-    if place_block.0 {
-        place_block.0 = false;
-        println!("{} - placing block", frame_num.0);
-    } else {
-        return;
-    }
-
-    let (active_entity, active_block, active_children) = match active_query.get_single() {
-        Ok(tup) => tup,
-        Err(_) => return,
-    };
+    for (active_entity, parent, active_block, active_children) in active_query.iter() {
+        let player = parent.0;
+        let (mut place_block, mut board_state, mut hold, mut streaks) =
+            match players.get_mut(player) {
+                Ok(ok) => ok,
+                Err(_) => continue,
+            };
+
+        if place_block.0 {
+            place_block.0 = false;
+            println!("{} - placing block", frame_num.0);
+        } else {
+            continue;
+        }
 
-    let ghost_entity = match ghost_query.get_single() {
-        Ok(tup) => tup,
-        Err(_) => return,
-    };
+        let ghost_entity = match ghost_query.iter().find(|(_, p)| p.0 == player) {
+            Some((ent, _)) => ent,
+            None => continue,
+        };
 
-    // if there's still room to move the block downwards...
-    if board_state.can_place(&active_block.movable.move_relative((0, -1).into())) {
-        // then bail out on finalizing block placement
-        println!("{} - room below block, bailing", frame_num.0);
-        return;
-    }
+        // if there's still room to move the block downwards...
+        if board_state.can_place(&active_block.movable.move_relative((0, -1).into())) {
+            // then bail out on finalizing block placement
+            println!("{} - room below block, bailing", frame_num.0);
+            continue;
+        }
 
-    // no more room to move the block down, finalize plcaement
-    board_state.place_block(&active_block.movable, &active_children[..]);
+        // no more room to move the block down, finalize plcaement
+        let spin = detect_spin(
+            board_state.as_ref(),
+            &active_block.movable,
+            active_block.last_move,
+        );
+        board_state.place_block(
+            &active_block.movable,
+            &active_children[..],
+            active_block.color,
+        );
+        hold.used = false;
+
+        // add absolute positioning to each placed cell
+        let rot = active_block.movable.rot();
+        for (pos, &child_ent) in active_block.movable.positions().zip(&active_children[..]) {
+            commands
+                .entity(child_ent)
+                .insert(AbsolutePositionedCell { pos, rot });
+        }
 
-    // add absolute positioning to each placed cell
-    let rot = active_block.movable.rot();
-    for (pos, &child_ent) in active_block.movable.positions().zip(&active_children[..]) {
+        // hand the children of the active over to the player, the board state
+        // effectively takes ownership of their placement once the parent TetrisBlock
+        // is despawned
         commands
-            .entity(child_ent)
-            .insert(AbsolutePositionedCell { pos, rot });
-    }
-
-    // orphan children of the active, the board state effectively takes
-    // ownership of their placement once the parent TetrisBlock is despawned
-    commands
-        .entity(active_entity)
-        .remove_children(active_children);
-
-    // and remove the tetris block entity
-    commands.entity(active_entity).despawn();
-
-    // remove the ghost entity and all its children recursively (they don't
-    // persist after block placement)
-    commands.entity(ghost_entity).despawn_recursive();
-
-    // check for any lines that were filled, and clear them
-    let (cleared, moved) = board_state.clear_filled_lines();
-    for ent in cleared {
-        commands.entity(ent).despawn_recursive();
-    }
-
-    // update absolute positions of cells that were moved on the board
-    for (ent, pos) in moved {
-        if let Ok(mut c) = cell_query.get_component_mut::<AbsolutePositionedCell>(ent) {
-            c.pos = pos;
+            .entity(active_entity)
+            .remove_children(active_children);
+        commands.entity(player).push_children(active_children);
+
+        // and remove the tetris block entity
+        commands.entity(active_entity).despawn_recursive();
+
+        // remove the ghost entity and all its children recursively (they don't
+        // persist after block placement)
+        commands.entity(ghost_entity).despawn_recursive();
+
+        // check for any lines that were filled, and clear them
+        let (cleared, moved) = board_state.clear_filled_lines();
+        let lines = cleared.len() / board_state.width();
+        let clear = streaks.record(lines, spin, lines > 0 && board_state.is_empty());
+        if clear.lines > 0 || clear.spin != Spin::None {
+            println!("{} - {:?}", frame_num.0, clear);
         }
+        placed.send(BlockPlaced { player, clear });
+
+        // the cleared cells go, and the rest drop into place, after the line clear
+        // delay. then the next block waits out the entry delay.
+        if !cleared.is_empty() {
+            commands.entity(player).insert(LineClearDelay {
+                timer: Timer::new(delays.line_clear, false),
+                cleared: cleared.into_iter().collect(),
+                moved: moved.into_iter().collect(),
+            });
+        }
+        commands
+            .entity(player)
+            .insert(EntryDelay(Timer::new(delays.entry, false)));
     }
 }
@@ -0,0 +1,959 @@
+// what a game is being played for. a mode sets how fast blocks fall and decides when
+// a player is done, and the best finished run is kept between games.
+
+use std::{collections::HashMap, fs, io, path::PathBuf, time::Duration};
+
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use rand::{thread_rng, Rng};
+
+use super::{
+    board::{Board, Cell, Fill},
+    cell_positioning::AbsolutePositionedCell,
+    delays::{EntryDelay, LineClearDelay},
+    garbage::{add_garbage_rows, GarbageQueue},
+    handle_block_actions,
+    line_clear::{LineClear, Spin, Streaks},
+    movable_block::BlockName,
+    piece_queue::PieceQueue,
+    place_block,
+    settings::Settings,
+    skin::Skin,
+    spawn_cell, BlockPlaced, Gravity, Hold, PlaceBlock, SkateTimer, Step, StepAction, TetrisBlock,
+    ToppedOut,
+};
+
+pub(super) const FONT: &str = "fonts/FiraMono-Medium.ttf";
+const STARTING_CELL_COLOR: Color = Color::DARK_GRAY;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Outcome {
+    Won,
+    Lost,
+}
+
+// how finished runs of a mode are compared
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Ranking {
+    FastestTime,
+    HighestScore,
+}
+
+pub trait GameMode: Send + Sync {
+    fn name(&self) -> &str;
+
+    // scales the points scored, and usually the gravity
+    fn level(&self, _progress: &Progress) -> u32 {
+        1
+    }
+
+    // how long the block takes to drop a row
+    fn gravity(&self, _progress: &Progress) -> Duration {
+        Duration::from_secs_f32(1.5)
+    }
+
+    // a split is taken every this many lines
+    fn split_every(&self) -> Option<usize> {
+        None
+    }
+
+    // checked every step, the game is over for the player once there's an outcome
+    fn outcome(&self, progress: &Progress) -> Option<Outcome>;
+
+    // what happens when there's no room for the next block
+    fn on_top_out(&self) -> TopOut {
+        TopOut::GameOver
+    }
+
+    // whether the clock runs
+    fn timed(&self) -> bool {
+        true
+    }
+
+    // None if runs aren't kept as personal bests
+    fn ranking(&self) -> Option<Ranking> {
+        None
+    }
+
+    // garbage the board starts out with
+    fn starting_garbage(&self) -> Option<Cheese> {
+        None
+    }
+
+    // cells the board starts out with
+    fn starting_cells(&self) -> Vec<IVec2> {
+        Vec::new()
+    }
+
+    // the pieces to play, in order, instead of random ones. there are no more once
+    // they're used up.
+    fn pieces(&self) -> Option<Vec<BlockName>> {
+        None
+    }
+
+    fn hold_allowed(&self) -> bool {
+        true
+    }
+
+    // shown under the finish screen
+    fn finish_note(&self) -> Option<&str> {
+        None
+    }
+
+    // whether a saved game can be picked up again with --resume, which finds the mode
+    // by its name
+    fn resumable(&self) -> bool {
+        true
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum TopOut {
+    GameOver,
+    // make room by taking away this many rows from the bottom, and keep going
+    ClearRows(usize),
+}
+
+// rows of garbage for a board to start with
+#[derive(Copy, Clone, Debug)]
+pub struct Cheese {
+    pub rows: usize,
+    // the chance of each row's hole not lining up with the one below, from 0 for a
+    // single well to 1 for a different column every row
+    pub messiness: f32,
+}
+
+// no goal, just play. what the game was before there were modes.
+pub struct FreePlay;
+impl GameMode for FreePlay {
+    fn name(&self) -> &str {
+        "free play"
+    }
+
+    fn outcome(&self, _progress: &Progress) -> Option<Outcome> {
+        None
+    }
+}
+
+// clear the lines as fast as possible
+pub struct Sprint {
+    pub lines: usize,
+}
+impl Default for Sprint {
+    fn default() -> Self {
+        Sprint { lines: 40 }
+    }
+}
+impl GameMode for Sprint {
+    fn name(&self) -> &str {
+        "sprint"
+    }
+
+    fn split_every(&self) -> Option<usize> {
+        Some(10)
+    }
+
+    fn outcome(&self, progress: &Progress) -> Option<Outcome> {
+        if progress.lines >= self.lines {
+            Some(Outcome::Won)
+        } else {
+            None
+        }
+    }
+
+    fn ranking(&self) -> Option<Ranking> {
+        Some(Ranking::FastestTime)
+    }
+}
+
+// score as much as possible before the time runs out
+pub struct Ultra {
+    pub time_limit: Duration,
+}
+impl Default for Ultra {
+    fn default() -> Self {
+        Ultra {
+            time_limit: Duration::from_secs(120),
+        }
+    }
+}
+impl GameMode for Ultra {
+    fn name(&self) -> &str {
+        "ultra"
+    }
+
+    fn outcome(&self, progress: &Progress) -> Option<Outcome> {
+        if progress.elapsed >= self.time_limit {
+            Some(Outcome::Won)
+        } else {
+            None
+        }
+    }
+
+    fn ranking(&self) -> Option<Ranking> {
+        Some(Ranking::HighestScore)
+    }
+}
+
+// the level goes up every 10 lines and the blocks fall faster, until the last line
+pub struct Marathon {
+    pub lines: usize,
+}
+impl Default for Marathon {
+    fn default() -> Self {
+        Marathon { lines: 150 }
+    }
+}
+impl GameMode for Marathon {
+    fn name(&self) -> &str {
+        "marathon"
+    }
+
+    fn level(&self, progress: &Progress) -> u32 {
+        (progress.lines / 10) as u32 + 1
+    }
+
+    // the guideline curve, a second a row at level 1 down to a few frames by 15
+    fn gravity(&self, progress: &Progress) -> Duration {
+        let level = self.level(progress).min(20) as f64;
+        Duration::from_secs_f64((0.8 - (level - 1.) * 0.007).powf(level - 1.))
+    }
+
+    fn split_every(&self) -> Option<usize> {
+        Some(10)
+    }
+
+    fn outcome(&self, progress: &Progress) -> Option<Outcome> {
+        if progress.lines >= self.lines {
+            Some(Outcome::Won)
+        } else {
+            None
+        }
+    }
+
+    fn ranking(&self) -> Option<Ranking> {
+        Some(Ranking::HighestScore)
+    }
+}
+
+// dig down through a board full of garbage
+pub struct Dig {
+    pub cheese: Cheese,
+}
+impl Default for Dig {
+    fn default() -> Self {
+        Dig {
+            cheese: Cheese {
+                rows: 6,
+                messiness: 0.5,
+            },
+        }
+    }
+}
+impl GameMode for Dig {
+    fn name(&self) -> &str {
+        "dig"
+    }
+
+    fn outcome(&self, progress: &Progress) -> Option<Outcome> {
+        if progress.pieces > 0 && progress.garbage_rows == 0 {
+            Some(Outcome::Won)
+        } else {
+            None
+        }
+    }
+
+    fn ranking(&self) -> Option<Ranking> {
+        Some(Ranking::FastestTime)
+    }
+
+    fn starting_garbage(&self) -> Option<Cheese> {
+        Some(self.cheese)
+    }
+}
+
+// endless, for warming up. the stack never gets too high, the bottom of the board
+// just goes away.
+pub struct Zen;
+impl GameMode for Zen {
+    fn name(&self) -> &str {
+        "zen"
+    }
+
+    fn outcome(&self, _progress: &Progress) -> Option<Outcome> {
+        None
+    }
+
+    fn on_top_out(&self) -> TopOut {
+        TopOut::ClearRows(4)
+    }
+
+    fn timed(&self) -> bool {
+        false
+    }
+}
+
+// the mode every player plays. insert a different one before adding the plugin to
+// change it.
+pub struct Mode(pub Box<dyn GameMode>);
+impl Default for Mode {
+    fn default() -> Self {
+        Mode(Box::new(FreePlay))
+    }
+}
+
+// the names modes go by on the command line and in the menu
+pub const MODE_NAMES: &[&str] = &["free", "sprint", "ultra", "marathon", "dig", "zen"];
+
+// also takes the names the modes give themselves, which is what saved games have
+pub fn mode_by_name(name: &str) -> Option<Box<dyn GameMode>> {
+    let mode: Box<dyn GameMode> = match name {
+        "free" | "free play" => Box::new(FreePlay),
+        "sprint" => Box::new(Sprint::default()),
+        "ultra" => Box::new(Ultra::default()),
+        "marathon" => Box::new(Marathon::default()),
+        "dig" => Box::new(Dig::default()),
+        "zen" => Box::new(Zen),
+        _ => return None,
+    };
+    Some(mode)
+}
+
+// how far along a player is
+#[derive(Component, Default, Clone, Debug, Serialize, Deserialize)]
+pub struct Progress {
+    pub lines: usize,
+    pub pieces: usize,
+    pub score: u32,
+    pub level: u32,
+    // rows on the board with garbage in them
+    pub garbage_rows: usize,
+    // the clock starts with the player's first input
+    pub started: bool,
+    pub elapsed: Duration,
+    // the time at each split, see GameMode::split_every
+    pub splits: Vec<Duration>,
+    pub topped_out: bool,
+    pub outcome: Option<Outcome>,
+    // every placement that cleared lines or was a spin
+    pub clears: Vec<LineClear>,
+}
+impl Progress {
+    fn record(&mut self, clear: &LineClear, split_every: Option<usize>) {
+        self.pieces += 1;
+        self.score += clear.score(self.level.max(1));
+        let before = self.lines;
+        self.lines += clear.lines;
+        if clear.lines > 0 || clear.spin != Spin::None {
+            self.clears.push(*clear);
+        }
+        if let Some(every) = split_every {
+            // one clear can cross more than one split
+            for _ in before / every..self.lines / every {
+                self.splits.push(self.elapsed);
+            }
+        }
+    }
+}
+
+// the best finished run of a mode
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct Record {
+    pub time_ms: u64,
+    pub splits_ms: Vec<u64>,
+    #[serde(default)]
+    pub score: u32,
+    #[serde(default)]
+    pub lines: usize,
+    #[serde(default)]
+    pub pieces: usize,
+}
+impl Record {
+    fn from_progress(progress: &Progress) -> Record {
+        Record {
+            time_ms: progress.elapsed.as_millis() as u64,
+            splits_ms: progress
+                .splits
+                .iter()
+                .map(|split| split.as_millis() as u64)
+                .collect(),
+            score: progress.score,
+            lines: progress.lines,
+            pieces: progress.pieces,
+        }
+    }
+
+    fn beats(&self, other: Option<&Record>, ranking: Ranking) -> bool {
+        match (ranking, other) {
+            (_, None) => true,
+            (Ranking::FastestTime, Some(other)) => self.time_ms < other.time_ms,
+            (Ranking::HighestScore, Some(other)) => self.score > other.score,
+        }
+    }
+}
+
+// personal bests by mode name, saved next to the user's other local data
+#[derive(Serialize, Deserialize, Default, Debug)]
+pub struct PersonalBests(HashMap<String, Record>);
+impl PersonalBests {
+    fn path() -> Option<PathBuf> {
+        dirs::data_local_dir().map(|dir| dir.join("knockoff-tetris").join("personal_bests.json"))
+    }
+
+    pub fn load() -> PersonalBests {
+        let contents = match Self::path().map(fs::read_to_string) {
+            Some(Ok(contents)) => contents,
+            _ => return PersonalBests::default(),
+        };
+        serde_json::from_str(&contents).unwrap_or_else(|err| {
+            println!("couldn't read personal bests, starting over: {}", err);
+            PersonalBests::default()
+        })
+    }
+
+    pub fn save(&self) -> io::Result<()> {
+        let path = Self::path()
+            .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "no local data directory"))?;
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        fs::write(path, serde_json::to_string_pretty(self).unwrap())
+    }
+
+    pub fn get(&self, mode: &str) -> Option<&Record> {
+        self.0.get(mode)
+    }
+}
+
+// a player who's done, no more blocks are spawned for them
+#[derive(Component)]
+pub struct Finished;
+
+// starts every player over with an empty board, in whatever the mode is now
+pub struct Restart;
+
+pub struct ModePlugin;
+impl Plugin for ModePlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<Mode>()
+            .insert_resource(PersonalBests::load())
+            .add_event::<Restart>()
+            .add_startup_system(set_up_boards)
+            .add_system(restart_game)
+            .add_system_to_stage(
+                "update_block_positions",
+                count_garbage_rows.after(place_block).before(track_progress),
+            )
+            .add_system_to_stage(
+                "update_block_positions",
+                tick_clock.before(handle_block_actions),
+            )
+            .add_system_to_stage(
+                "update_block_positions",
+                handle_top_out.before(track_progress),
+            )
+            .add_system_to_stage("update_block_positions", track_progress.after(place_block));
+    }
+}
+
+fn tick_clock(
+    mode: Res<Mode>,
+    step: Res<Step>,
+    mut actions: EventReader<StepAction>,
+    mut players: Query<&mut Progress, Without<Finished>>,
+) {
+    if !mode.0.timed() {
+        return;
+    }
+    for action in actions.iter() {
+        if let Ok(mut progress) = players.get_mut(action.player) {
+            progress.started = true;
+        }
+    }
+    for mut progress in players.iter_mut() {
+        if progress.started {
+            progress.elapsed += step.delta;
+        }
+    }
+}
+
+fn set_up_boards(
+    mut commands: Commands,
+    mode: Res<Mode>,
+    skin: Res<Skin>,
+    mut players: Query<(
+        Entity,
+        &mut Board,
+        &mut PieceQueue,
+        &mut GarbageQueue,
+        &mut Hold,
+    )>,
+    mut cell_query: Query<&mut AbsolutePositionedCell>,
+) {
+    for (player, mut board_state, mut piece_queue, mut garbage_queue, mut hold) in
+        players.iter_mut()
+    {
+        set_up_board(
+            &mut commands,
+            &skin,
+            &*mode.0,
+            player,
+            (
+                &mut board_state,
+                &mut piece_queue,
+                &mut garbage_queue,
+                &mut hold,
+            ),
+            &mut cell_query,
+        );
+    }
+}
+
+// whatever the mode starts a player off with
+fn set_up_board(
+    commands: &mut Commands,
+    skin: &Skin,
+    mode: &dyn GameMode,
+    player: Entity,
+    (board_state, piece_queue, garbage_queue, hold): (
+        &mut Board,
+        &mut PieceQueue,
+        &mut GarbageQueue,
+        &mut Hold,
+    ),
+    cell_query: &mut Query<&mut AbsolutePositionedCell>,
+) {
+    if let Some(pieces) = mode.pieces() {
+        *piece_queue = PieceQueue::fixed(pieces);
+    }
+    hold.disabled = !mode.hold_allowed();
+
+    let mut cells = Vec::new();
+    for pos in mode.starting_cells() {
+        let ent = spawn_cell(commands, skin, pos, Fill::Garbage, STARTING_CELL_COLOR);
+        *board_state.cell_mut(pos) = Some(Cell {
+            ent,
+            fill: Fill::Garbage,
+            color: STARTING_CELL_COLOR,
+        });
+        cells.push(ent);
+    }
+    commands.entity(player).push_children(&cells);
+
+    if let Some(cheese) = mode.starting_garbage() {
+        let width = board_state.width();
+        let holes = garbage_queue.messy_rows(cheese.rows, width, cheese.messiness);
+        add_garbage_rows(commands, skin, player, board_state, &holes, cell_query);
+    }
+}
+
+#[allow(clippy::type_complexity)]
+fn restart_game(
+    mut commands: Commands,
+    mode: Res<Mode>,
+    settings: Res<Settings>,
+    skin: Res<Skin>,
+    mut restart: EventReader<Restart>,
+    mut players: Query<(
+        Entity,
+        &mut Board,
+        &mut PieceQueue,
+        &mut GarbageQueue,
+        &mut Hold,
+        &mut Streaks,
+        &mut Progress,
+        &mut PlaceBlock,
+    )>,
+    mut cell_query: Query<&mut AbsolutePositionedCell>,
+) {
+    if restart.iter().count() == 0 {
+        return;
+    }
+    println!("restarting {}", mode.0.name());
+    // everyone gets the same pieces again, but not the ones from last time
+    let seed = thread_rng().gen();
+    for (
+        player,
+        mut board_state,
+        mut piece_queue,
+        mut garbage_queue,
+        mut hold,
+        mut streaks,
+        mut progress,
+        mut place_block,
+    ) in players.iter_mut()
+    {
+        // the board's cells, the block in play and the finish screen
+        commands.entity(player).despawn_descendants();
+        commands
+            .entity(player)
+            .remove::<Finished>()
+            .remove::<SkateTimer>()
+            .remove::<LineClearDelay>()
+            .remove::<EntryDelay>();
+        *board_state = Board::new(board_state.width(), board_state.height());
+        *piece_queue = PieceQueue::new(seed, &settings.pieces, settings.randomizer);
+        *garbage_queue = GarbageQueue::new(seed);
+        *hold = Hold::default();
+        *streaks = Streaks::default();
+        *progress = Progress::default();
+        place_block.0 = false;
+        set_up_board(
+            &mut commands,
+            &skin,
+            &*mode.0,
+            player,
+            (
+                &mut board_state,
+                &mut piece_queue,
+                &mut garbage_queue,
+                &mut hold,
+            ),
+            &mut cell_query,
+        );
+    }
+}
+
+fn count_garbage_rows(mut players: Query<(&Board, &mut Progress)>) {
+    for (board_state, mut progress) in players.iter_mut() {
+        let mut rows: Vec<_> = board_state
+            .iter_cells()
+            .filter(|(_, cell)| cell.fill == Fill::Garbage)
+            .map(|(pos, _)| pos.y)
+            .collect();
+        rows.sort_unstable();
+        rows.dedup();
+        let rows = rows.len();
+        if progress.garbage_rows != rows {
+            progress.garbage_rows = rows;
+        }
+    }
+}
+
+fn handle_top_out(
+    mut commands: Commands,
+    mode: Res<Mode>,
+    mut topped_out: EventReader<ToppedOut>,
+    mut players: Query<(&mut Progress, &mut Board)>,
+    mut cell_query: Query<&mut AbsolutePositionedCell>,
+) {
+    for &ToppedOut { player } in topped_out.iter() {
+        let (mut progress, mut board_state) = match players.get_mut(player) {
+            Ok(ok) => ok,
+            Err(_) => continue,
+        };
+        match mode.0.on_top_out() {
+            TopOut::GameOver => progress.topped_out = true,
+            TopOut::ClearRows(rows) => {
+                println!("topped out, clearing the bottom {} rows", rows);
+                let (cleared, moved) = board_state.clear_bottom_rows(rows);
+                for ent in cleared {
+                    commands.entity(ent).despawn_recursive();
+                }
+                for (ent, pos) in moved {
+                    if let Ok(mut c) = cell_query.get_mut(ent) {
+                        c.pos = pos;
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+fn track_progress(
+    mut commands: Commands,
+    mode: Res<Mode>,
+    settings: Res<Settings>,
+    mut bests: ResMut<PersonalBests>,
+    asset_server: Res<AssetServer>,
+    mut placed: EventReader<BlockPlaced>,
+    mut players: Query<(Entity, &mut Progress, &mut Gravity), Without<Finished>>,
+    block_query: Query<(Entity, &Parent), With<TetrisBlock>>,
+) {
+    let mode = &*mode.0;
+    for &BlockPlaced { player, clear } in placed.iter() {
+        let (_, mut progress, _) = match players.get_mut(player) {
+            Ok(ok) => ok,
+            Err(_) => continue,
+        };
+        let splits = progress.splits.len();
+        progress.record(&clear, mode.split_every());
+        for (idx, split) in progress.splits.iter().enumerate().skip(splits) {
+            println!("split {} - {}", idx + 1, format_time(*split));
+        }
+    }
+
+    for (player, mut progress, mut gravity) in players.iter_mut() {
+        let level = mode.level(&progress);
+        if progress.level != level {
+            if progress.level > 0 {
+                println!("level {}", level);
+            }
+            progress.level = level;
+        }
+        let speed = mode.gravity(&progress).mul_f32(settings.gravity_scale);
+        if gravity.0.duration() != speed {
+            gravity.0.set_duration(speed);
+        }
+
+        let outcome = if progress.topped_out {
+            Outcome::Lost
+        } else {
+            match mode.outcome(&progress) {
+                Some(outcome) => outcome,
+                None => continue,
+            }
+        };
+        progress.outcome = Some(outcome);
+        println!(
+            "{} over - {:?} in {} with {} lines and {} points",
+            mode.name(),
+            outcome,
+            format_time(progress.elapsed),
+            progress.lines,
+            progress.score
+        );
+
+        // the game stops where it is, take away whatever block was in play
+        commands
+            .entity(player)
+            .insert(Finished)
+            .remove::<SkateTimer>();
+        for (block, parent) in block_query.iter() {
+            if parent.0 == player {
+                commands.entity(block).despawn_recursive();
+            }
+        }
+
+        let record = Record::from_progress(&progress);
+        let best = bests.get(mode.name()).cloned();
+        let new_best = match mode.ranking() {
+            Some(ranking) if outcome == Outcome::Won => record.beats(best.as_ref(), ranking),
+            _ => false,
+        };
+        if new_best {
+            bests.0.insert(mode.name().to_string(), record.clone());
+            if let Err(err) = bests.save() {
+                println!("couldn't save personal bests: {}", err);
+            }
+        }
+
+        let text = finish_text(mode, outcome, &record, best.as_ref(), new_best);
+        let finish_screen = commands
+            .spawn_bundle(Text2dBundle {
+                text: Text::with_section(
+                    text,
+                    TextStyle {
+                        font: asset_server.load(FONT),
+                        font_size: 20.,
+                        color: Color::WHITE,
+                    },
+                    TextAlignment {
+                        vertical: VerticalAlign::Center,
+                        horizontal: HorizontalAlign::Center,
+                    },
+                ),
+                transform: Transform::from_xyz(0., 0., 50.),
+                ..default()
+            })
+            .id();
+        commands.entity(player).add_child(finish_screen);
+    }
+}
+
+// the time, then each split against the personal best's
+fn finish_text(
+    mode: &dyn GameMode,
+    outcome: Outcome,
+    record: &Record,
+    best: Option<&Record>,
+    new_best: bool,
+) -> String {
+    let title = match outcome {
+        Outcome::Won => "complete",
+        Outcome::Lost => "game over",
+    };
+    let mut text = format!(
+        "{} {}\n\n{}\n",
+        mode.name(),
+        title,
+        format_time(Duration::from_millis(record.time_ms))
+    );
+    if mode.ranking() == Some(Ranking::HighestScore) {
+        text.push_str(&format!("{} points\n", record.score));
+        if let Some(best) = best {
+            text.push_str(&format!("best {} points\n", best.score));
+        }
+    }
+    text.push_str(&format!(
+        "{} lines, {} pieces\n",
+        record.lines, record.pieces
+    ));
+    if new_best {
+        text.push_str("new personal best!\n");
+    }
+    if let Some(note) = mode.finish_note() {
+        text.push_str(&format!("\n{}\n", note));
+    }
+    for (idx, &split) in record.splits_ms.iter().enumerate() {
+        text.push_str(&format!(
+            "\n{:>2}  {}",
+            (idx + 1) * mode.split_every().unwrap_or(0),
+            format_time(Duration::from_millis(split))
+        ));
+        if let Some(&best_split) = best.and_then(|best| best.splits_ms.get(idx)) {
+            text.push_str(&format!("  {}", format_diff(split, best_split)));
+        }
+    }
+    text
+}
+
+// m:ss.mmm
+pub fn format_time(time: Duration) -> String {
+    let millis = time.as_millis();
+    format!(
+        "{}:{:02}.{:03}",
+        millis / 60_000,
+        millis / 1000 % 60,
+        millis % 1000
+    )
+}
+
+fn format_diff(time_ms: u64, best_ms: u64) -> String {
+    let (sign, diff) = if time_ms < best_ms {
+        ('-', best_ms - time_ms)
+    } else {
+        ('+', time_ms - best_ms)
+    };
+    format!("{}{}.{:03}", sign, diff / 1000, diff % 1000)
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::Duration;
+
+    use crate::tetris_block::line_clear::{LineClear, Spin};
+
+    use super::{
+        format_diff, format_time, Dig, GameMode, Marathon, Outcome, Progress, Ranking, Record,
+        Sprint, TopOut, Ultra, Zen,
+    };
+
+    fn clear(lines: usize) -> LineClear {
+        LineClear {
+            lines,
+            spin: Spin::None,
+            perfect_clear: false,
+            combo: 0,
+            back_to_back: false,
+        }
+    }
+
+    #[test]
+    fn test_sprint() {
+        let sprint = Sprint::default();
+        let mut progress = Progress::default();
+        for secs in 1..=9 {
+            progress.elapsed = Duration::from_secs(secs);
+            progress.record(&clear(4), sprint.split_every());
+            assert_eq!(sprint.outcome(&progress), None);
+        }
+        assert_eq!(progress.lines, 36);
+        assert_eq!(
+            progress.splits,
+            vec![
+                Duration::from_secs(3),
+                Duration::from_secs(5),
+                Duration::from_secs(8)
+            ]
+        );
+
+        progress.elapsed = Duration::from_secs(10);
+        progress.record(&clear(4), sprint.split_every());
+        assert_eq!(sprint.outcome(&progress), Some(Outcome::Won));
+        assert_eq!(progress.splits.len(), 4);
+        assert_eq!(progress.pieces, 10);
+    }
+
+    #[test]
+    fn test_records() {
+        let record = |time_ms, score| Record {
+            time_ms,
+            splits_ms: vec![],
+            score,
+            lines: 0,
+            pieces: 0,
+        };
+        assert!(record(1000, 0).beats(None, Ranking::FastestTime));
+        assert!(record(1000, 0).beats(Some(&record(1001, 0)), Ranking::FastestTime));
+        assert!(!record(1000, 0).beats(Some(&record(1000, 0)), Ranking::FastestTime));
+        assert!(record(1000, 10).beats(Some(&record(10, 5)), Ranking::HighestScore));
+        assert!(!record(10, 5).beats(Some(&record(1000, 10)), Ranking::HighestScore));
+
+        assert_eq!(format_time(Duration::from_millis(83_456)), "1:23.456");
+        assert_eq!(format_time(Duration::from_millis(5)), "0:00.005");
+        assert_eq!(format_diff(9_500, 10_000), "-0.500");
+        assert_eq!(format_diff(12_345, 10_000), "+2.345");
+    }
+
+    #[test]
+    fn test_ultra_and_marathon() {
+        let ultra = Ultra::default();
+        let mut progress = Progress {
+            elapsed: Duration::from_secs(119),
+            ..Default::default()
+        };
+        assert_eq!(ultra.outcome(&progress), None);
+        progress.elapsed = Duration::from_secs(120);
+        assert_eq!(ultra.outcome(&progress), Some(Outcome::Won));
+
+        let marathon = Marathon::default();
+        let mut progress = Progress::default();
+        assert_eq!(marathon.level(&progress), 1);
+        assert_eq!(marathon.gravity(&progress), Duration::from_secs(1));
+        progress.lines = 25;
+        assert_eq!(marathon.level(&progress), 3);
+        assert!(marathon.gravity(&progress) < Duration::from_secs_f32(0.7));
+        progress.lines = 150;
+        assert_eq!(marathon.outcome(&progress), Some(Outcome::Won));
+
+        // points scale with the level the clear was made at
+        let mut progress = Progress {
+            level: 3,
+            ..Default::default()
+        };
+        progress.record(&clear(4), None);
+        assert_eq!(progress.score, 2400);
+    }
+
+    #[test]
+    fn test_dig() {
+        let dig = Dig::default();
+        let mut progress = Progress {
+            garbage_rows: 0,
+            ..Default::default()
+        };
+        // nothing's been dug before the first piece
+        assert_eq!(dig.outcome(&progress), None);
+        progress.garbage_rows = 2;
+        progress.record(&clear(1), None);
+        assert_eq!(dig.outcome(&progress), None);
+        progress.garbage_rows = 0;
+        assert_eq!(dig.outcome(&progress), Some(Outcome::Won));
+    }
+
+    #[test]
+    fn test_zen() {
+        let mut progress = Progress {
+            lines: 1000,
+            elapsed: Duration::from_secs(1000),
+            ..Default::default()
+        };
+        progress.record(&clear(4), Zen.split_every());
+        assert_eq!(Zen.outcome(&progress), None);
+        assert_eq!(Zen.on_top_out(), TopOut::ClearRows(4));
+        assert_eq!(Sprint::default().on_top_out(), TopOut::GameOver);
+    }
+}
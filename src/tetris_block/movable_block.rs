@@ -11,8 +11,9 @@ use bevy::{
     prelude::{default, Component, Transform},
 };
 use lazy_static::lazy_static;
+use serde::{Deserialize, Serialize};
 
-#[derive(Copy, Clone, Debug)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
 pub enum BlockName {
     L,
     J,
@@ -26,14 +27,14 @@ pub enum BlockName {
 impl BlockName {
     pub fn create_movable(&self, at_pos: IVec2) -> MovableBlock {
         match self {
-            BlockName::L => MovableBlock::new(at_pos, &L_SHAPE_CONFIG),
-            BlockName::J => MovableBlock::new(at_pos, &J_SHAPE_CONFIG),
-            BlockName::O => MovableBlock::new(at_pos, &O_SHAPE_CONFIG),
-            BlockName::I => MovableBlock::new(at_pos, &I_SHAPE_CONFIG),
-            BlockName::T => MovableBlock::new(at_pos, &T_SHAPE_CONFIG),
-            BlockName::S => MovableBlock::new(at_pos, &S_SHAPE_CONFIG),
-            BlockName::Z => MovableBlock::new(at_pos, &Z_SHAPE_CONFIG),
-            BlockName::Test => MovableBlock::new(at_pos, &DOT_CONFIG),
+            BlockName::L => MovableBlock::new(at_pos, *self, &L_SHAPE_CONFIG),
+            BlockName::J => MovableBlock::new(at_pos, *self, &J_SHAPE_CONFIG),
+            BlockName::O => MovableBlock::new(at_pos, *self, &O_SHAPE_CONFIG),
+            BlockName::I => MovableBlock::new(at_pos, *self, &I_SHAPE_CONFIG),
+            BlockName::T => MovableBlock::new(at_pos, *self, &T_SHAPE_CONFIG),
+            BlockName::S => MovableBlock::new(at_pos, *self, &S_SHAPE_CONFIG),
+            BlockName::Z => MovableBlock::new(at_pos, *self, &Z_SHAPE_CONFIG),
+            BlockName::Test => MovableBlock::new(at_pos, *self, &DOT_CONFIG),
         }
     }
 }
@@ -49,25 +50,40 @@ pub type Kicks = &'static [IVec2];
 #[derive(Component, Clone)]
 pub struct MovableBlock {
     pub definition: &'static BlockDefinition,
+    name: BlockName,
     position: IVec2,
     rotation: u8,
     rotation_continuous: i32,
 }
 
 impl MovableBlock {
-    pub fn new(position: IVec2, definition: &'static BlockDefinition) -> MovableBlock {
+    pub fn new(
+        position: IVec2,
+        name: BlockName,
+        definition: &'static BlockDefinition,
+    ) -> MovableBlock {
         MovableBlock {
             definition,
+            name,
             position,
             rotation: 0,
             rotation_continuous: 0,
         }
     }
 
+    pub fn name(&self) -> BlockName {
+        self.name
+    }
+
     pub fn rot(&self) -> i32 {
         self.rotation_continuous
     }
 
+    // index into definition.rotations, unlike `rot` this wraps around
+    pub fn rotation_state(&self) -> usize {
+        self.rotation as usize
+    }
+
     pub fn rotation(&self) -> f32 {
         if self.definition.rotations.len() == 1 {
             return 0.;
@@ -0,0 +1,197 @@
+use std::collections::{HashMap, HashSet, VecDeque};
+
+use bevy::math::IVec2;
+use serde::{Deserialize, Serialize};
+
+use super::movable_block::{MovableBlock, RotDir};
+
+// anything blocks can be moved around on
+pub trait Collision {
+    fn can_place(&self, block: &MovableBlock) -> bool;
+    // cells off the board count as occupied
+    fn is_occupied(&self, loc: IVec2) -> bool;
+}
+
+// an input that can be applied to the active block, mirroring the player's controls
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum Move {
+    Left,
+    Right,
+    RotateLeft,
+    RotateRight,
+    // drops the block as far as it will go without locking it, same as the down key
+    SoftDrop,
+}
+
+// a position the block can be locked at, and the shortest list of moves which
+// gets it there from where it started
+#[derive(Clone)]
+pub struct Placement {
+    pub block: MovableBlock,
+    pub moves: Vec<Move>,
+}
+
+// applies a single move to the block, returning None if the board doesn't leave room
+// for it (or, for soft drops, if the block is already resting on something)
+pub fn apply_move(board: &impl Collision, block: &MovableBlock, mv: Move) -> Option<MovableBlock> {
+    match mv {
+        Move::Left => nudge(board, block, (-1, 0).into()),
+        Move::Right => nudge(board, block, (1, 0).into()),
+        Move::RotateLeft => rotate(board, block, RotDir::Left),
+        Move::RotateRight => rotate(board, block, RotDir::Right),
+        Move::SoftDrop => {
+            let mut dropped = nudge(board, block, (0, -1).into())?;
+            while let Some(next) = nudge(board, &dropped, (0, -1).into()) {
+                dropped = next;
+            }
+            Some(dropped)
+        }
+    }
+}
+
+fn nudge(board: &impl Collision, block: &MovableBlock, dir: IVec2) -> Option<MovableBlock> {
+    let moved = block.move_relative(dir);
+    if board.can_place(&moved) {
+        Some(moved)
+    } else {
+        None
+    }
+}
+
+// tries each of the kicks for the rotation in order, the first one that fits wins
+fn rotate(board: &impl Collision, block: &MovableBlock, dir: RotDir) -> Option<MovableBlock> {
+    let (rotated, kicks) = block.clone().rotate(dir);
+    kicks
+        .iter()
+        .map(|&kick| rotated.move_relative(kick))
+        .find(|kicked| board.can_place(kicked))
+}
+
+pub fn is_resting(board: &impl Collision, block: &MovableBlock) -> bool {
+    !board.can_place(&block.move_relative((0, -1).into()))
+}
+
+const ALL_MOVES: &[Move] = &[
+    Move::Left,
+    Move::Right,
+    Move::RotateLeft,
+    Move::RotateRight,
+    Move::SoftDrop,
+];
+
+type StateKey = (IVec2, usize);
+fn state_key(block: &MovableBlock) -> StateKey {
+    (block.root_position(), block.rotation_state())
+}
+
+// every distinct set of cells the block can be locked into, found with a breadth
+// first search over block states so the moves for each placement are as short as
+// possible. placements covering the same cells (e.g. the two horizontal rotations of
+// an I block) are only reported once.
+pub fn reachable_placements(board: &impl Collision, block: &MovableBlock) -> Vec<Placement> {
+    if !board.can_place(block) {
+        return Vec::new();
+    }
+
+    // state -> the state it was reached from, and how
+    let mut visited: HashMap<StateKey, Option<(StateKey, Move)>> = HashMap::new();
+    let mut queue = VecDeque::new();
+    let mut seen_cells = HashSet::new();
+    let mut placements = Vec::new();
+
+    visited.insert(state_key(block), None);
+    queue.push_back(block.clone());
+
+    while let Some(current) = queue.pop_front() {
+        let current_key = state_key(&current);
+
+        if is_resting(board, &current) && seen_cells.insert(sorted_cells(&current)) {
+            placements.push(Placement {
+                block: current.clone(),
+                moves: moves_to(&visited, current_key),
+            });
+        }
+
+        for &mv in ALL_MOVES {
+            let next = match apply_move(board, &current, mv) {
+                Some(next) => next,
+                None => continue,
+            };
+            let next_key = state_key(&next);
+            if visited.contains_key(&next_key) {
+                continue;
+            }
+            visited.insert(next_key, Some((current_key, mv)));
+            queue.push_back(next);
+        }
+    }
+
+    placements
+}
+
+// the cells covered by the block, in a consistent order so they can be compared
+pub fn sorted_cells(block: &MovableBlock) -> Vec<IVec2> {
+    let mut cells: Vec<IVec2> = block.positions().collect();
+    cells.sort_by_key(|pos| (pos.y, pos.x));
+    cells
+}
+
+// the shortest path from the block to a placement covering `cells`, if one is reachable
+pub fn path_to(board: &impl Collision, block: &MovableBlock, cells: &[IVec2]) -> Option<Placement> {
+    reachable_placements(board, block)
+        .into_iter()
+        .find(|placement| sorted_cells(&placement.block) == cells)
+}
+
+fn moves_to(visited: &HashMap<StateKey, Option<(StateKey, Move)>>, mut key: StateKey) -> Vec<Move> {
+    let mut moves = Vec::new();
+    while let Some((parent, mv)) = visited[&key] {
+        moves.push(mv);
+        key = parent;
+    }
+    moves.reverse();
+    moves
+}
+
+#[cfg(test)]
+mod test {
+    use bevy::{ecs::entity::Entity, math::IVec2};
+
+    use crate::tetris_block::{
+        board::{Board, Cell},
+        movable_block::BlockName,
+    };
+
+    use super::{reachable_placements, Move};
+
+    #[test]
+    fn test_open_board() {
+        let board = Board::new(8, 12);
+        let block = BlockName::I.create_movable((4, 9).into());
+        // 5 horizontal columns, 8 vertical ones
+        assert_eq!(reachable_placements(&board, &block).len(), 13);
+
+        let block = BlockName::O.create_movable((4, 9).into());
+        assert_eq!(reachable_placements(&board, &block).len(), 7);
+    }
+
+    #[test]
+    fn test_tuck() {
+        // ......
+        // ████..  <- overhang, the dot has to drop first then slide under it
+        // ......
+        let mut board = Board::new(3, 3);
+        *board.cell_mut((0, 1).into()) = Some(Cell::garbage(Entity::from_raw(0)));
+        *board.cell_mut((1, 1).into()) = Some(Cell::garbage(Entity::from_raw(1)));
+
+        let block = BlockName::Test.create_movable((2, 2).into());
+        let placements = reachable_placements(&board, &block);
+        assert_eq!(placements.len(), 5);
+
+        let tucked = placements
+            .iter()
+            .find(|p| p.block.root_position() == IVec2::new(0, 0))
+            .unwrap();
+        assert_eq!(tucked.moves, vec![Move::SoftDrop, Move::Left, Move::Left]);
+    }
+}
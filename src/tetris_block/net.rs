@@ -0,0 +1,509 @@
+// online versus over tcp. both sides run the whole game for both players and only
+// the inputs are sent across, so the simulation has to come out the same on each end.
+// every tick is held back until the inputs from both sides are in (lockstep), and
+// the boards are hashed every so often to catch the two games drifting apart.
+
+use std::{
+    collections::BTreeMap,
+    io::{self, BufRead, BufReader, Read, Write},
+    net::{TcpListener, TcpStream},
+    sync::{mpsc, Mutex},
+    thread,
+    time::Duration,
+};
+
+use bevy::prelude::*;
+use rand::{thread_rng, Rng};
+use serde::{Deserialize, Serialize};
+
+use super::{
+    bit_board::BitBoard, board::Board, movable_block::BlockName, piece_queue::Randomizer,
+    read_player_actions, settings::Settings, BlockAction, Player, PlayerAction, Step, StepAction,
+};
+
+// bumped whenever the messages or the simulation change in a way older builds
+// wouldn't understand
+const PROTOCOL_VERSION: u32 = 3;
+// how many ticks ahead local inputs are sent, to hide the round trip
+const INPUT_DELAY: u64 = 3;
+// how much game time each tick moves forward by
+const TICK: Duration = Duration::from_micros(16_667);
+// how often the boards are compared, in ticks
+const HASH_EVERY: u64 = 60;
+
+// the settings that have to match on both ends for the games to stay in sync
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+struct SharedSettings {
+    width: usize,
+    height: usize,
+    input_delay: u64,
+    pieces: Vec<BlockName>,
+    randomizer: Randomizer,
+    gravity_scale: f32,
+    lock_delay_ms: u64,
+    line_clear_delay_ms: u64,
+    entry_delay_ms: u64,
+}
+impl SharedSettings {
+    fn new(settings: &Settings) -> SharedSettings {
+        SharedSettings {
+            width: settings.board_width,
+            height: settings.board_height,
+            input_delay: INPUT_DELAY,
+            pieces: settings.pieces.clone(),
+            randomizer: settings.randomizer,
+            gravity_scale: settings.gravity_scale,
+            lock_delay_ms: settings.lock_delay_ms,
+            line_clear_delay_ms: settings.line_clear_delay_ms,
+            entry_delay_ms: settings.entry_delay_ms,
+        }
+    }
+}
+
+// sent as one json object per line
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum NetMessage {
+    Hello {
+        game_version: String,
+        protocol: u32,
+        settings: SharedSettings,
+    },
+    Welcome {
+        seed: u64,
+    },
+    Reject {
+        reason: String,
+    },
+    // the actions made on one end during a tick
+    Inputs {
+        tick: u64,
+        actions: Vec<BlockAction>,
+    },
+    BoardHash {
+        tick: u64,
+        hash: u64,
+    },
+}
+
+fn hello(settings: &Settings) -> NetMessage {
+    NetMessage::Hello {
+        game_version: env!("CARGO_PKG_VERSION").to_string(),
+        protocol: PROTOCOL_VERSION,
+        settings: SharedSettings::new(settings),
+    }
+}
+
+// the host's side of the handshake, the reason the other end can't play if it can't
+fn check_hello(message: &NetMessage, settings: &Settings) -> Result<(), String> {
+    let ours = SharedSettings::new(settings);
+    match message {
+        NetMessage::Hello {
+            game_version,
+            protocol,
+            settings,
+        } => {
+            if *protocol != PROTOCOL_VERSION {
+                Err(format!(
+                    "protocol version {} doesn't match the host's {} (game version {} vs {})",
+                    protocol,
+                    PROTOCOL_VERSION,
+                    game_version,
+                    env!("CARGO_PKG_VERSION")
+                ))
+            } else if *settings != ours {
+                Err(format!(
+                    "settings {:?} don't match the host's {:?}",
+                    settings, ours
+                ))
+            } else {
+                Ok(())
+            }
+        }
+        other => Err(format!("expected hello, got {:?}", other)),
+    }
+}
+
+fn send(stream: &mut TcpStream, message: &NetMessage) -> io::Result<()> {
+    let line = serde_json::to_string(message).unwrap();
+    writeln!(stream, "{}", line)
+}
+
+// reads a byte at a time, so nothing after the line gets buffered away from the
+// reader thread started later on
+fn recv(stream: &mut TcpStream) -> io::Result<NetMessage> {
+    let mut line = Vec::new();
+    let mut byte = [0];
+    loop {
+        if stream.read(&mut byte)? == 0 {
+            return Err(io::ErrorKind::UnexpectedEof.into());
+        }
+        if byte[0] == b'\n' {
+            break;
+        }
+        line.push(byte[0]);
+    }
+    serde_json::from_slice(&line).map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))
+}
+
+// a connection to the other player, made before the app starts
+pub struct Session {
+    stream: TcpStream,
+    seed: u64,
+    // which board is played from here, the host is on the left
+    pub local_player: usize,
+}
+impl Session {
+    // waits for someone to join on the given port, with the same settings
+    pub fn host(port: u16, settings: &Settings) -> io::Result<Session> {
+        let listener = TcpListener::bind(("0.0.0.0", port))?;
+        println!("net - waiting for a player on port {}", port);
+        loop {
+            let (mut stream, addr) = listener.accept()?;
+            stream.set_nodelay(true)?;
+            let message = recv(&mut stream)?;
+            match check_hello(&message, settings) {
+                Ok(()) => {
+                    let seed = thread_rng().gen();
+                    send(&mut stream, &NetMessage::Welcome { seed })?;
+                    println!("net - {} joined", addr);
+                    return Ok(Session {
+                        stream,
+                        seed,
+                        local_player: 0,
+                    });
+                }
+                Err(reason) => {
+                    println!("net - turned away {}: {}", addr, reason);
+                    let _ = send(&mut stream, &NetMessage::Reject { reason });
+                }
+            }
+        }
+    }
+
+    pub fn join(addr: &str, settings: &Settings) -> io::Result<Session> {
+        let mut stream = TcpStream::connect(addr)?;
+        stream.set_nodelay(true)?;
+        send(&mut stream, &hello(settings))?;
+        match recv(&mut stream)? {
+            NetMessage::Welcome { seed } => {
+                println!("net - joined {}", addr);
+                Ok(Session {
+                    stream,
+                    seed,
+                    local_player: 1,
+                })
+            }
+            NetMessage::Reject { reason } => Err(io::Error::new(
+                io::ErrorKind::ConnectionRefused,
+                format!("the host turned us away: {}", reason),
+            )),
+            other => Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("expected welcome, got {:?}", other),
+            )),
+        }
+    }
+
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
+
+    pub fn remote_player(&self) -> usize {
+        1 - self.local_player
+    }
+}
+
+struct Lockstep {
+    stream: TcpStream,
+    // None once the other end has gone away
+    messages: Mutex<mpsc::Receiver<Option<NetMessage>>>,
+    local_player: usize,
+    // the next tick to be simulated
+    tick: u64,
+    // the next tick local inputs will be sent for
+    sent: u64,
+    // made since the last inputs were sent
+    pending: Vec<BlockAction>,
+    // each player's actions, by tick and then player index
+    inputs: BTreeMap<u64, [Option<Vec<BlockAction>>; 2]>,
+    local_hashes: BTreeMap<u64, u64>,
+    remote_hashes: BTreeMap<u64, u64>,
+    // set once the game can't go on
+    stopped: bool,
+}
+impl Lockstep {
+    fn new(session: &Session) -> Lockstep {
+        let stream = session
+            .stream
+            .try_clone()
+            .expect("couldn't clone the stream");
+        let reader = session
+            .stream
+            .try_clone()
+            .expect("couldn't clone the stream");
+        let (tx, rx) = mpsc::channel();
+        thread::spawn(move || {
+            for line in BufReader::new(reader).lines() {
+                let line = match line {
+                    Ok(line) => line,
+                    Err(_) => break,
+                };
+                match serde_json::from_str(&line) {
+                    Ok(message) => {
+                        if tx.send(Some(message)).is_err() {
+                            return;
+                        }
+                    }
+                    Err(err) => println!("net - couldn't parse {:?}: {}", line, err),
+                }
+            }
+            let _ = tx.send(None);
+        });
+
+        // nobody has had a chance to act in the ticks before the first inputs arrive
+        let inputs = (0..INPUT_DELAY)
+            .map(|tick| (tick, [Some(Vec::new()), Some(Vec::new())]))
+            .collect();
+        Lockstep {
+            stream,
+            messages: Mutex::new(rx),
+            local_player: session.local_player,
+            tick: 0,
+            sent: INPUT_DELAY,
+            pending: Vec::new(),
+            inputs,
+            local_hashes: BTreeMap::new(),
+            remote_hashes: BTreeMap::new(),
+            stopped: false,
+        }
+    }
+
+    fn send(&mut self, message: &NetMessage) {
+        if let Err(err) = send(&mut self.stream, message) {
+            println!("net - lost the connection: {}", err);
+            self.stopped = true;
+        }
+    }
+
+    fn receive(&mut self, message: NetMessage) {
+        let remote = 1 - self.local_player;
+        match message {
+            NetMessage::Inputs { tick, actions } => {
+                self.inputs.entry(tick).or_default()[remote] = Some(actions);
+            }
+            NetMessage::BoardHash { tick, hash } => {
+                self.remote_hashes.insert(tick, hash);
+            }
+            other => println!("net - unexpected {:?}", other),
+        }
+    }
+
+    // compares whichever hashes both sides have made so far
+    fn check_hashes(&mut self) {
+        while let Some((&tick, &hash)) = self.remote_hashes.iter().next() {
+            let ours = match self.local_hashes.get(&tick) {
+                Some(&ours) => ours,
+                None => break,
+            };
+            if ours != hash {
+                println!(
+                    "net - desync at tick {}: boards hash to {:x} here and {:x} there",
+                    tick, ours, hash
+                );
+                self.stopped = true;
+            }
+            self.remote_hashes.remove(&tick);
+            self.local_hashes.remove(&tick);
+        }
+    }
+}
+
+pub struct NetPlugin {
+    pub session: Session,
+}
+impl Plugin for NetPlugin {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(Lockstep::new(&self.session))
+            .add_system(exchange_inputs.after(read_player_actions))
+            .add_system_to_stage(CoreStage::PostUpdate, check_board_hashes);
+    }
+}
+
+// sends off the local inputs, and lets the game move forward a tick once both
+// players' inputs for it are in
+fn exchange_inputs(
+    mut lockstep: ResMut<Lockstep>,
+    mut step: ResMut<Step>,
+    mut actions: EventReader<PlayerAction>,
+    mut step_actions: EventWriter<StepAction>,
+    players: Query<(Entity, &Player)>,
+) {
+    step.run = false;
+    // bots and the keyboard only send for the local player
+    lockstep
+        .pending
+        .extend(actions.iter().map(|action| action.action));
+    if lockstep.stopped {
+        return;
+    }
+
+    let messages: Vec<_> = lockstep.messages.lock().unwrap().try_iter().collect();
+    for message in messages {
+        match message {
+            Some(message) => lockstep.receive(message),
+            None => {
+                println!("net - the other player left");
+                lockstep.stopped = true;
+                return;
+            }
+        }
+    }
+
+    if lockstep.sent <= lockstep.tick + INPUT_DELAY {
+        let tick = lockstep.sent;
+        let actions = std::mem::take(&mut lockstep.pending);
+        lockstep.send(&NetMessage::Inputs {
+            tick,
+            actions: actions.clone(),
+        });
+        let local = lockstep.local_player;
+        lockstep.inputs.entry(tick).or_default()[local] = Some(actions);
+        lockstep.sent += 1;
+    }
+
+    let tick = lockstep.tick;
+    let ready = matches!(lockstep.inputs.get(&tick), Some([Some(_), Some(_)]));
+    if !ready {
+        return;
+    }
+    let inputs = lockstep.inputs.remove(&tick).unwrap();
+
+    // the same order on both ends
+    let mut players: Vec<_> = players.iter().collect();
+    players.sort_by_key(|(_, player)| player.index);
+    for ((player, _), actions) in players.into_iter().zip(inputs) {
+        for action in actions.unwrap() {
+            step_actions.send(StepAction { player, action });
+        }
+    }
+
+    lockstep.tick += 1;
+    step.run = true;
+    step.delta = TICK;
+}
+
+fn check_board_hashes(
+    mut lockstep: ResMut<Lockstep>,
+    step: Res<Step>,
+    players: Query<(&Player, &Board)>,
+) {
+    // the tick that just ran
+    let tick = lockstep.tick.wrapping_sub(1);
+    if !step.run || !tick.is_multiple_of(HASH_EVERY) {
+        return;
+    }
+
+    let mut boards: Vec<_> = players.iter().collect();
+    boards.sort_by_key(|(player, _)| player.index);
+    let boards: Vec<BitBoard> = boards
+        .into_iter()
+        .map(|(_, board_state)| BitBoard::from(board_state))
+        .collect();
+    let hash = hash_boards(&boards);
+
+    lockstep.send(&NetMessage::BoardHash { tick, hash });
+    lockstep.local_hashes.insert(tick, hash);
+    lockstep.check_hashes();
+}
+
+// fnv-1a over each board's size and rows, written out byte by byte so both ends
+// agree whatever rust version or platform they were built with
+fn hash_boards(boards: &[BitBoard]) -> u64 {
+    const OFFSET_BASIS: u64 = 0xcbf2_9ce4_8422_2325;
+    const PRIME: u64 = 0x0100_0000_01b3;
+    fn fnv1a(hash: u64, bytes: &[u8]) -> u64 {
+        bytes
+            .iter()
+            .fold(hash, |hash, &byte| (hash ^ byte as u64).wrapping_mul(PRIME))
+    }
+
+    let mut hash = OFFSET_BASIS;
+    for board in boards {
+        hash = fnv1a(hash, &(board.width() as u32).to_le_bytes());
+        hash = fnv1a(hash, &(board.height() as u32).to_le_bytes());
+        for row in board.rows() {
+            hash = fnv1a(hash, &row.to_le_bytes());
+        }
+    }
+    hash
+}
+
+#[cfg(test)]
+mod test {
+    use bevy::math::IVec2;
+
+    use super::{check_hello, hash_boards, hello, NetMessage, SharedSettings, PROTOCOL_VERSION};
+    use crate::tetris_block::{move_gen::Move, settings::Settings, BitBoard, BlockAction};
+
+    #[test]
+    fn test_hash_boards() {
+        let mut board = BitBoard::new(4, 2);
+        board.set_occupied(IVec2::new(0, 0));
+        board.set_occupied(IVec2::new(3, 1));
+        // the same on every build, or versus would see desyncs that aren't there
+        assert_eq!(hash_boards(&[board.clone()]), 0x1789_6913_7106_0f2a);
+
+        let mut other = board.clone();
+        other.set_occupied(IVec2::new(1, 0));
+        assert_ne!(hash_boards(&[board.clone()]), hash_boards(&[other.clone()]));
+        assert_ne!(
+            hash_boards(&[board.clone(), other.clone()]),
+            hash_boards(&[other, board])
+        );
+    }
+
+    #[test]
+    fn test_check_hello() {
+        let settings = Settings::default();
+        assert!(check_hello(&hello(&settings), &settings).is_ok());
+
+        let mismatched = NetMessage::Hello {
+            game_version: "0.0.0".to_string(),
+            protocol: PROTOCOL_VERSION,
+            settings: SharedSettings {
+                width: 10,
+                ..SharedSettings::new(&settings)
+            },
+        };
+        assert!(check_hello(&mismatched, &settings).is_err());
+
+        // different controls don't matter
+        let mut rebound = Settings::default();
+        rebound.controls.single.clear();
+        assert!(check_hello(&hello(&rebound), &settings).is_ok());
+
+        let old = NetMessage::Hello {
+            game_version: "0.0.0".to_string(),
+            protocol: PROTOCOL_VERSION + 1,
+            settings: SharedSettings::new(&settings),
+        };
+        assert!(check_hello(&old, &settings).is_err());
+        assert!(check_hello(&NetMessage::Welcome { seed: 1 }, &settings).is_err());
+    }
+
+    #[test]
+    fn test_message_round_trip() {
+        let message = NetMessage::Inputs {
+            tick: 7,
+            actions: vec![
+                BlockAction::Move(Move::RotateLeft),
+                BlockAction::Hold,
+                BlockAction::HardDrop,
+            ],
+        };
+        let line = serde_json::to_string(&message).unwrap();
+        assert!(!line.contains('\n'));
+        assert_eq!(serde_json::from_str::<NetMessage>(&line).unwrap(), message);
+    }
+}
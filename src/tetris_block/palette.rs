@@ -0,0 +1,116 @@
+// the colours blocks are drawn with. `random` paints each block with one of the
+// settings' colours, the others give every piece a colour of its own.
+
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use super::{movable_block::BlockName, Fill};
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Palette {
+    Random,
+    // the usual colour for each piece
+    Standard,
+    // bright on the dark background, and still apart with deuteranopia or protanopia
+    HighContrast,
+}
+impl Palette {
+    pub const ALL: [Palette; 3] = [Palette::Random, Palette::Standard, Palette::HighContrast];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Palette::Random => "random",
+            Palette::Standard => "standard",
+            Palette::HighContrast => "high contrast",
+        }
+    }
+
+    // what a cell filled with `fill` is drawn with, if the palette decides
+    pub fn color(&self, fill: Fill) -> Option<Color> {
+        match self {
+            Palette::Random => None,
+            Palette::Standard => Some(fill.color()),
+            Palette::HighContrast => Some(high_contrast(fill)),
+        }
+    }
+}
+
+// mostly the okabe-ito colours, with the t a pale pink to keep it apart from the j
+fn high_contrast(fill: Fill) -> Color {
+    let hex = match fill {
+        Fill::Piece(BlockName::I) => "56b4e9",
+        Fill::Piece(BlockName::O) => "f0e442",
+        Fill::Piece(BlockName::T) => "ffccee",
+        Fill::Piece(BlockName::S) => "009e73",
+        Fill::Piece(BlockName::Z) => "d55e00",
+        Fill::Piece(BlockName::J) => "0072b2",
+        Fill::Piece(BlockName::L) => "e69f00",
+        Fill::Piece(BlockName::Test) | Fill::Garbage => "b0b0b0",
+    };
+    Color::hex(hex).unwrap()
+}
+
+#[cfg(test)]
+mod test {
+    use bevy::prelude::*;
+
+    use crate::tetris_block::{movable_block::BlockName, Fill};
+
+    use super::Palette;
+
+    // how the colour looks without red or green cones (vienot, brettel and mollon)
+    fn simulate(color: Color, matrix: Option<[[f32; 3]; 3]>) -> Vec3 {
+        let [r, g, b, _] = color.as_linear_rgba_f32();
+        let rgb = match matrix {
+            None => Vec3::new(r, g, b),
+            Some(m) => Vec3::new(
+                m[0][0] * r + m[0][1] * g + m[0][2] * b,
+                m[1][0] * r + m[1][1] * g + m[1][2] * b,
+                m[2][0] * r + m[2][1] * g + m[2][2] * b,
+            ),
+        };
+        let rgb = rgb.clamp(Vec3::ZERO, Vec3::ONE);
+        let [r, g, b, _] = Color::rgb_linear(rgb.x, rgb.y, rgb.z).as_rgba_f32();
+        Vec3::new(r, g, b)
+    }
+
+    #[test]
+    fn test_high_contrast() {
+        let fills: Vec<Fill> = [
+            BlockName::I,
+            BlockName::O,
+            BlockName::T,
+            BlockName::S,
+            BlockName::Z,
+            BlockName::J,
+            BlockName::L,
+        ]
+        .into_iter()
+        .map(Fill::Piece)
+        .chain([Fill::Garbage])
+        .collect();
+        let protanopia = [
+            [0.11238, 0.88762, 0.],
+            [0.11238, 0.88762, 0.],
+            [0.00401, -0.00401, 1.],
+        ];
+        let deuteranopia = [
+            [0.29275, 0.70725, 0.],
+            [0.29275, 0.70725, 0.],
+            [-0.02234, 0.02234, 1.],
+        ];
+        for matrix in [None, Some(protanopia), Some(deuteranopia)] {
+            let colors: Vec<Vec3> = fills
+                .iter()
+                .map(|&fill| simulate(Palette::HighContrast.color(fill).unwrap(), matrix))
+                .collect();
+            for (i, a) in colors.iter().enumerate() {
+                for b in &colors[i + 1..] {
+                    assert!(a.distance(*b) > 0.2, "{:?} and {:?} look alike", a, b);
+                }
+            }
+        }
+        assert_eq!(Palette::Random.color(Fill::Garbage), None);
+    }
+}
@@ -0,0 +1,139 @@
+use std::collections::VecDeque;
+
+use bevy::prelude::Component;
+use rand::{seq::SliceRandom, Rng, SeedableRng};
+use rand_chacha::ChaCha12Rng;
+use serde::{Deserialize, Serialize};
+
+use super::movable_block::BlockName;
+
+// how many upcoming pieces are known ahead of time
+pub const PREVIEW_LEN: usize = 5;
+
+// how the next piece is picked
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Randomizer {
+    // any of the pieces, every time
+    Random,
+    // each of the pieces once, in a shuffled bag, then a new bag
+    Bag,
+}
+
+// picks the blocks to spawn, keeping a few upcoming ones around so they can be
+// previewed (or planned for by the ai)
+#[derive(Component)]
+pub struct PieceQueue {
+    rng: ChaCha12Rng,
+    // empty for a fixed sequence, which runs out
+    pieces: Vec<BlockName>,
+    randomizer: Randomizer,
+    // what's left of the bag
+    bag: Vec<BlockName>,
+    upcoming: VecDeque<BlockName>,
+}
+impl PieceQueue {
+    pub fn new(seed: u64, pieces: &[BlockName], randomizer: Randomizer) -> PieceQueue {
+        assert!(!pieces.is_empty());
+        let mut queue = PieceQueue {
+            rng: ChaCha12Rng::seed_from_u64(seed),
+            pieces: pieces.to_vec(),
+            randomizer,
+            bag: Vec::new(),
+            upcoming: VecDeque::with_capacity(PREVIEW_LEN + 1),
+        };
+        queue.fill();
+        queue
+    }
+
+    // just these pieces in this order, then nothing
+    pub fn fixed(sequence: Vec<BlockName>) -> PieceQueue {
+        PieceQueue {
+            rng: ChaCha12Rng::seed_from_u64(0),
+            pieces: Vec::new(),
+            randomizer: Randomizer::Random,
+            bag: Vec::new(),
+            upcoming: sequence.into(),
+        }
+    }
+
+    pub fn pop(&mut self) -> BlockName {
+        let next = self.upcoming.pop_front().unwrap();
+        self.fill();
+        next
+    }
+
+    pub fn upcoming(&self) -> impl ExactSizeIterator<Item = BlockName> + '_ {
+        self.upcoming.iter().copied()
+    }
+
+    pub fn save(&self) -> SavedQueue {
+        SavedQueue {
+            rng: self.rng.clone(),
+            upcoming: self.upcoming.iter().copied().collect(),
+            endless: !self.pieces.is_empty(),
+            bag: self.bag.clone(),
+        }
+    }
+
+    // picks up where the saved queue left off, with these pieces if it was endless
+    pub fn restore(saved: SavedQueue, pieces: &[BlockName], randomizer: Randomizer) -> PieceQueue {
+        PieceQueue {
+            rng: saved.rng,
+            pieces: if saved.endless {
+                pieces.to_vec()
+            } else {
+                Vec::new()
+            },
+            randomizer,
+            bag: saved.bag,
+            upcoming: saved.upcoming.into(),
+        }
+    }
+
+    fn fill(&mut self) {
+        if self.pieces.is_empty() {
+            return;
+        }
+        while self.upcoming.len() < PREVIEW_LEN {
+            let block = match self.randomizer {
+                Randomizer::Random => self.pieces[self.rng.gen_range(0..self.pieces.len())],
+                Randomizer::Bag => {
+                    if self.bag.is_empty() {
+                        self.bag = self.pieces.clone();
+                        self.bag.shuffle(&mut self.rng);
+                    }
+                    self.bag.pop().unwrap()
+                }
+            };
+            self.upcoming.push_back(block);
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct SavedQueue {
+    rng: ChaCha12Rng,
+    upcoming: Vec<BlockName>,
+    endless: bool,
+    // saved before there were bags
+    #[serde(default)]
+    bag: Vec<BlockName>,
+}
+
+#[cfg(test)]
+mod test {
+    use crate::tetris_block::{env::STANDARD_BLOCKS, movable_block::BlockName};
+
+    use super::{PieceQueue, Randomizer};
+
+    #[test]
+    fn test_bag() {
+        let mut queue = PieceQueue::new(3, STANDARD_BLOCKS, Randomizer::Bag);
+        for _ in 0..3 {
+            let mut bag: Vec<BlockName> = (0..STANDARD_BLOCKS.len()).map(|_| queue.pop()).collect();
+            bag.sort_by_key(|name| STANDARD_BLOCKS.iter().position(|other| other == name));
+            assert_eq!(bag, STANDARD_BLOCKS);
+        }
+    }
+}
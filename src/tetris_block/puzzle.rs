@@ -0,0 +1,411 @@
+// set up boards to solve with a fixed set of pieces. a puzzle is a text file with a
+// few `key: value` lines and the board drawn the way Board's Debug prints it:
+//
+//     # lines starting with a '#' are comments
+//     name: first t-spin
+//     goal: t-spin double
+//     pieces: T
+//     hold: no
+//
+//     ................
+//     ██..........████
+//     ████....████████
+//     ██████..████████
+//
+// the board is drawn top down, two characters a cell, "██" (or "[]") for a filled cell
+// and ".." for an empty one. rows not drawn above the top one are empty.
+// goals are "clear <n> lines", "perfect clear" and "t-spin single/double/triple".
+
+use std::{fs, path::Path};
+
+use bevy::prelude::*;
+
+use super::{
+    mode::{GameMode, Mode, Outcome, Progress, Restart},
+    movable_block::BlockName,
+    Spin,
+};
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Goal {
+    Lines(usize),
+    PerfectClear,
+    // a t-spin clearing this many lines
+    TSpin(usize),
+}
+impl Goal {
+    fn parse(text: &str) -> Result<Goal, String> {
+        let text = text.to_lowercase();
+        let words: Vec<_> = text.split_whitespace().collect();
+        let goal = match words.as_slice() {
+            ["perfect", "clear"] => Goal::PerfectClear,
+            ["clear", lines, "line" | "lines"] => Goal::Lines(
+                lines
+                    .parse()
+                    .map_err(|_| format!("{:?} isn't a number of lines", lines))?,
+            ),
+            ["t-spin" | "tspin", lines] => Goal::TSpin(match *lines {
+                "single" => 1,
+                "double" => 2,
+                "triple" => 3,
+                _ => return Err(format!("no such thing as a t-spin {}", lines)),
+            }),
+            _ => return Err(format!("unknown goal {:?}", text)),
+        };
+        Ok(goal)
+    }
+
+    pub fn met(&self, progress: &Progress) -> bool {
+        match *self {
+            Goal::Lines(lines) => progress.lines >= lines,
+            Goal::PerfectClear => progress.clears.iter().any(|clear| clear.perfect_clear),
+            Goal::TSpin(lines) => progress
+                .clears
+                .iter()
+                .any(|clear| clear.spin == Spin::Full && clear.lines == lines),
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Puzzle {
+    pub name: String,
+    // the filled cells the board starts with
+    pub cells: Vec<IVec2>,
+    pub pieces: Vec<BlockName>,
+    pub hold: bool,
+    pub goal: Goal,
+}
+impl Puzzle {
+    // the name is left empty if the file doesn't give one
+    pub fn parse(text: &str, width: usize, height: usize) -> Result<Puzzle, String> {
+        let mut name = String::new();
+        let mut goal = None;
+        let mut pieces = Vec::new();
+        let mut hold = true;
+        let mut rows = Vec::new();
+
+        for line in text.lines() {
+            let line = line.trim();
+            if line.is_empty()
+                || line.starts_with('#')
+                || line.starts_with("Board State")
+                || line.chars().all(|c| c == '-')
+            {
+                continue;
+            }
+            if let Some((key, value)) = line.split_once(':') {
+                let value = value.trim();
+                match key.trim() {
+                    "name" => name = value.to_string(),
+                    "goal" => goal = Some(Goal::parse(value)?),
+                    "pieces" => {
+                        pieces = value
+                            .chars()
+                            .filter(|c| !c.is_whitespace() && *c != ',')
+                            .map(parse_piece)
+                            .collect::<Result<_, _>>()?
+                    }
+                    "hold" => {
+                        hold = match value {
+                            "yes" | "true" => true,
+                            "no" | "false" => false,
+                            _ => return Err(format!("hold should be yes or no, not {:?}", value)),
+                        }
+                    }
+                    other => return Err(format!("unknown setting {:?}", other)),
+                }
+                continue;
+            }
+            rows.push(parse_row(line, width)?);
+        }
+
+        let goal = goal.ok_or("the puzzle needs a goal")?;
+        if pieces.is_empty() {
+            return Err("the puzzle needs some pieces".to_string());
+        }
+        if rows.len() > height {
+            return Err(format!(
+                "the puzzle has {} rows, the board only has {}",
+                rows.len(),
+                height
+            ));
+        }
+        // drawn top down, the last row is the bottom of the board
+        let mut cells = Vec::new();
+        for (y, row) in rows.iter().rev().enumerate() {
+            if row.iter().all(|&filled| filled) {
+                return Err(format!("row {} from the bottom is already full", y + 1));
+            }
+            for (x, &filled) in row.iter().enumerate() {
+                if filled {
+                    cells.push(IVec2::new(x as i32, y as i32));
+                }
+            }
+        }
+
+        Ok(Puzzle {
+            name,
+            cells,
+            pieces,
+            hold,
+            goal,
+        })
+    }
+}
+
+fn parse_piece(c: char) -> Result<BlockName, String> {
+    Ok(match c.to_ascii_uppercase() {
+        'I' => BlockName::I,
+        'O' => BlockName::O,
+        'T' => BlockName::T,
+        'S' => BlockName::S,
+        'Z' => BlockName::Z,
+        'J' => BlockName::J,
+        'L' => BlockName::L,
+        _ => return Err(format!("unknown piece {:?}", c)),
+    })
+}
+
+fn parse_row(line: &str, width: usize) -> Result<Vec<bool>, String> {
+    let chars: Vec<_> = line.chars().collect();
+    if chars.len() != width * 2 {
+        return Err(format!(
+            "{:?} should be {} cells, two characters each",
+            line, width
+        ));
+    }
+    chars
+        .chunks(2)
+        .map(|cell| match cell {
+            ['█', '█'] | ['[', ']'] => Ok(true),
+            ['.', '.'] => Ok(false),
+            _ => Err(format!("{:?} in {:?} isn't a cell", cell, line)),
+        })
+        .collect()
+}
+
+// a puzzle file, or every .txt file in a directory in order of name
+pub fn load_puzzles(path: &Path, width: usize, height: usize) -> Result<Vec<Puzzle>, String> {
+    let mut files = if path.is_dir() {
+        fs::read_dir(path)
+            .map_err(|err| format!("couldn't read {}: {}", path.display(), err))?
+            .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+            .filter(|file| file.extension() == Some("txt".as_ref()))
+            .collect()
+    } else {
+        vec![path.to_path_buf()]
+    };
+    files.sort();
+
+    files
+        .iter()
+        .map(|file| {
+            let text = fs::read_to_string(file)
+                .map_err(|err| format!("couldn't read {}: {}", file.display(), err))?;
+            let mut puzzle = Puzzle::parse(&text, width, height)
+                .map_err(|err| format!("{}: {}", file.display(), err))?;
+            if puzzle.name.is_empty() {
+                puzzle.name = file
+                    .file_stem()
+                    .map_or("puzzle".into(), |stem| stem.to_string_lossy().into());
+            }
+            Ok(puzzle)
+        })
+        .collect()
+}
+
+impl GameMode for Puzzle {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn outcome(&self, progress: &Progress) -> Option<Outcome> {
+        if self.goal.met(progress) {
+            Some(Outcome::Won)
+        } else if progress.pieces >= self.pieces.len() {
+            Some(Outcome::Lost)
+        } else {
+            None
+        }
+    }
+
+    fn starting_cells(&self) -> Vec<IVec2> {
+        self.cells.clone()
+    }
+
+    fn pieces(&self) -> Option<Vec<BlockName>> {
+        Some(self.pieces.clone())
+    }
+
+    fn hold_allowed(&self) -> bool {
+        self.hold
+    }
+
+    fn finish_note(&self) -> Option<&str> {
+        Some("R to retry, N for the next puzzle")
+    }
+
+    // the board, pieces and goal come from the puzzle file, not the name
+    fn resumable(&self) -> bool {
+        false
+    }
+}
+
+struct Puzzles {
+    list: Vec<Puzzle>,
+    current: usize,
+}
+
+// plays through the puzzles in order. R starts the current one over and N skips to
+// the next.
+pub struct PuzzlePlugin {
+    pub puzzles: Vec<Puzzle>,
+}
+impl Plugin for PuzzlePlugin {
+    fn build(&self, app: &mut App) {
+        assert!(!self.puzzles.is_empty(), "there are no puzzles to play");
+        println!(
+            "puzzle 1 of {} - {}",
+            self.puzzles.len(),
+            self.puzzles[0].name
+        );
+        app.insert_resource(Mode(Box::new(self.puzzles[0].clone())))
+            .insert_resource(Puzzles {
+                list: self.puzzles.clone(),
+                current: 0,
+            })
+            .add_system(switch_puzzles);
+    }
+}
+
+fn switch_puzzles(
+    kb: Res<Input<KeyCode>>,
+    mut puzzles: ResMut<Puzzles>,
+    mut mode: ResMut<Mode>,
+    mut restart: EventWriter<Restart>,
+) {
+    if kb.just_pressed(KeyCode::N) {
+        puzzles.current = (puzzles.current + 1) % puzzles.list.len();
+        let puzzle = &puzzles.list[puzzles.current];
+        println!(
+            "puzzle {} of {} - {}",
+            puzzles.current + 1,
+            puzzles.list.len(),
+            puzzle.name
+        );
+        mode.0 = Box::new(puzzle.clone());
+        restart.send(Restart);
+    } else if kb.just_pressed(KeyCode::R) {
+        restart.send(Restart);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use bevy::math::IVec2;
+
+    use crate::{
+        tetris_block::{
+            line_clear::{LineClear, Spin},
+            mode::{GameMode, Outcome, Progress},
+            movable_block::BlockName,
+        },
+        GRID_CELLS,
+    };
+
+    use super::{load_puzzles, Goal, Puzzle};
+
+    #[test]
+    fn test_parse() {
+        let text = "
+            # from the debug print
+            name: tsd
+            goal: T-spin double
+            pieces: T, I
+            hold: no
+
+            Board State(12)
+            --------
+            []......
+            []..[][]
+            --------
+        ";
+        let puzzle = Puzzle::parse(text, 4, 12).unwrap();
+        assert_eq!(puzzle.name, "tsd");
+        assert_eq!(puzzle.goal, Goal::TSpin(2));
+        assert_eq!(puzzle.pieces, vec![BlockName::T, BlockName::I]);
+        assert!(!puzzle.hold);
+        assert_eq!(
+            puzzle.cells,
+            vec![
+                IVec2::new(0, 0),
+                IVec2::new(2, 0),
+                IVec2::new(3, 0),
+                IVec2::new(0, 1)
+            ]
+        );
+
+        let puzzle = Puzzle::parse("goal: clear 4 lines\npieces: I\n██......", 4, 1).unwrap();
+        assert_eq!(puzzle.goal, Goal::Lines(4));
+        assert!(puzzle.hold);
+        assert_eq!(puzzle.cells, vec![IVec2::new(0, 0)]);
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        let parse = |text| Puzzle::parse(text, 2, 2);
+        assert!(parse("pieces: T\n..[]").is_err());
+        assert!(parse("goal: perfect clear\n..[]").is_err());
+        assert!(parse("goal: win\npieces: T").is_err());
+        assert!(parse("goal: perfect clear\npieces: X").is_err());
+        assert!(parse("goal: perfect clear\npieces: T\n..[]..").is_err());
+        assert!(parse("goal: perfect clear\npieces: T\n[][]").is_err());
+        assert!(parse("goal: perfect clear\npieces: T\n....\n....\n....").is_err());
+        assert!(parse("goal: perfect clear\npieces: T\n....\n..[]").is_ok());
+    }
+
+    #[test]
+    fn test_bundled_puzzles() {
+        let puzzles = load_puzzles(
+            "assets/puzzles".as_ref(),
+            GRID_CELLS.width as usize,
+            GRID_CELLS.height as usize,
+        )
+        .unwrap();
+        assert_eq!(puzzles.len(), 3);
+        assert_eq!(puzzles[0].name, "t-spin double");
+    }
+
+    #[test]
+    fn test_goals() {
+        let puzzle = Puzzle::parse("goal: t-spin double\npieces: TT", 4, 4).unwrap();
+        let mut progress = Progress::default();
+        assert_eq!(puzzle.outcome(&progress), None);
+
+        let clear = |lines, spin| LineClear {
+            lines,
+            spin,
+            perfect_clear: false,
+            combo: 0,
+            back_to_back: false,
+        };
+        progress.clears.push(clear(2, Spin::Mini));
+        progress.pieces = 1;
+        assert_eq!(puzzle.outcome(&progress), None);
+        progress.pieces = 2;
+        assert_eq!(puzzle.outcome(&progress), Some(Outcome::Lost));
+        progress.clears.push(clear(2, Spin::Full));
+        assert_eq!(puzzle.outcome(&progress), Some(Outcome::Won));
+
+        progress.lines = 3;
+        assert!(Goal::Lines(3).met(&progress));
+        assert!(!Goal::Lines(4).met(&progress));
+        assert!(!Goal::PerfectClear.met(&progress));
+        progress.clears.push(LineClear {
+            perfect_clear: true,
+            ..clear(1, Spin::None)
+        });
+        assert!(Goal::PerfectClear.met(&progress));
+    }
+}
@@ -0,0 +1,349 @@
+// the game in progress, saved to a file when the game is closed (or F5 is pressed)
+// and picked up again with --resume. games that are over aren't saved.
+
+use std::{fs, io, path::PathBuf, time::Duration};
+
+use bevy::{app::AppExit, prelude::*};
+use serde::{Deserialize, Serialize};
+
+use super::{
+    board::{Board, Cell, Fill},
+    garbage::GarbageQueue,
+    line_clear::Streaks,
+    menu::GameState,
+    mode::{Finished, GameMode, Mode, Progress},
+    movable_block::{BlockName, MovableBlock, RotDir},
+    piece_queue::{PieceQueue, SavedQueue},
+    settings::Settings,
+    skate_timer::SkateTimer,
+    skin::Skin,
+    spawn_block, spawn_cell, Ghost, Gravity, Hold, Paused, Player, TetrisBlock,
+};
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct SavedGame {
+    // the name of the mode being played
+    pub mode: String,
+    pub players: Vec<SavedPlayer>,
+}
+impl SavedGame {
+    pub fn path() -> Option<PathBuf> {
+        dirs::data_local_dir().map(|dir| dir.join("knockoff-tetris").join("save.json"))
+    }
+
+    pub fn load() -> Result<SavedGame, String> {
+        let path = Self::path().ok_or("no local data directory")?;
+        let contents = fs::read_to_string(&path)
+            .map_err(|err| format!("couldn't read {}: {}", path.display(), err))?;
+        serde_json::from_str(&contents)
+            .map_err(|err| format!("couldn't read {}: {}", path.display(), err))
+    }
+
+    fn save(&self) -> io::Result<()> {
+        let path = Self::path()
+            .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "no local data directory"))?;
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        fs::write(path, serde_json::to_string(self).unwrap())
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct SavedPlayer {
+    index: usize,
+    cells: Vec<SavedCell>,
+    active: Option<SavedPiece>,
+    hold: Hold,
+    queue: SavedQueue,
+    garbage: GarbageQueue,
+    streaks: Streaks,
+    progress: Progress,
+    // how far into the current row the gravity and skate timers are
+    gravity: Duration,
+    skate: Option<Duration>,
+    paused: bool,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+struct SavedCell {
+    pos: [i32; 2],
+    fill: Fill,
+    color: [f32; 4],
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+struct SavedPiece {
+    name: BlockName,
+    pos: [i32; 2],
+    // how many times it's been turned right from how it spawned
+    rotation: usize,
+    color: [f32; 4],
+}
+impl SavedPiece {
+    fn to_movable(&self) -> MovableBlock {
+        let mut movable = self.name.create_movable(IVec2::ZERO);
+        for _ in 0..self.rotation {
+            movable = movable.rotate(RotDir::Right).0;
+        }
+        movable.move_relative(IVec2::from(self.pos) - movable.root_position())
+    }
+}
+
+// `resume` is put back in place of the new game
+pub struct SavePlugin {
+    pub resume: Option<SavedGame>,
+}
+impl Plugin for SavePlugin {
+    fn build(&self, app: &mut App) {
+        if let Some(saved) = &self.resume {
+            app.insert_resource(Resume(Some(saved.clone())))
+                // after the mode has set up the boards, so they can be replaced
+                .add_startup_system_to_stage(StartupStage::PostStartup, resume_game);
+        }
+        app.add_system_to_stage(CoreStage::Last, save_game);
+    }
+}
+
+struct Resume(Option<SavedGame>);
+
+// why the game shouldn't be saved, if it shouldn't
+fn not_saving(state: &GameState, mode: &dyn GameMode, finished: bool) -> Option<&'static str> {
+    // quitting to the menu leaves the game, and there may not have been one yet
+    if !matches!(state, GameState::Playing | GameState::Paused) {
+        Some("not playing")
+    } else if finished {
+        Some("the game is over")
+    } else if !mode.resumable() {
+        Some("puzzles can't be resumed")
+    } else {
+        None
+    }
+}
+
+#[allow(clippy::type_complexity)]
+fn save_game(
+    kb: Res<Input<KeyCode>>,
+    mut exit: EventReader<AppExit>,
+    state: Res<State<GameState>>,
+    mode: Res<Mode>,
+    players: Query<(
+        Entity,
+        &Player,
+        &Board,
+        (&PieceQueue, &GarbageQueue, &Hold, &Streaks, &Progress),
+        (&Gravity, &Paused, Option<&SkateTimer>, Option<&Finished>),
+    )>,
+    active_query: Query<(&Parent, &TetrisBlock), Without<Ghost>>,
+) {
+    let exiting = exit.iter().count() > 0;
+    if !kb.just_pressed(KeyCode::F5) && !exiting {
+        return;
+    }
+    let finished = players
+        .iter()
+        .any(|(.., (_, _, _, finished))| finished.is_some());
+    if let Some(reason) = not_saving(state.current(), &*mode.0, finished) {
+        println!("{}, not saving", reason);
+        return;
+    }
+
+    let mut saved = SavedGame {
+        mode: mode.0.name().to_string(),
+        players: Vec::new(),
+    };
+    for (player, &Player { index }, board_state, queues, timers) in players.iter() {
+        let (piece_queue, garbage_queue, hold, streaks, progress) = queues;
+        let (gravity, paused, skate, _) = timers;
+        let active = active_query
+            .iter()
+            .find(|(parent, ..)| parent.0 == player)
+            .map(|(_, block)| SavedPiece {
+                name: block.movable.name(),
+                pos: block.movable.root_position().into(),
+                rotation: block.movable.rotation_state(),
+                color: block.color.as_rgba_f32(),
+            });
+        saved.players.push(SavedPlayer {
+            index,
+            cells: board_state
+                .iter_cells()
+                .map(|(pos, cell)| SavedCell {
+                    pos: pos.into(),
+                    fill: cell.fill,
+                    color: cell.color.as_rgba_f32(),
+                })
+                .collect(),
+            active,
+            hold: hold.clone(),
+            queue: piece_queue.save(),
+            garbage: garbage_queue.clone(),
+            streaks: streaks.clone(),
+            progress: progress.clone(),
+            gravity: gravity.0.elapsed(),
+            skate: skate.map(|skate| skate.0.elapsed()),
+            paused: paused.0,
+        });
+    }
+    match saved.save() {
+        Ok(()) => println!("saved the game"),
+        Err(err) => println!("couldn't save the game: {}", err),
+    }
+}
+
+#[allow(clippy::type_complexity)]
+fn resume_game(
+    mut commands: Commands,
+    mut resume: ResMut<Resume>,
+    settings: Res<Settings>,
+    skin: Res<Skin>,
+    mut players: Query<(
+        Entity,
+        &Player,
+        &mut Board,
+        (
+            &mut PieceQueue,
+            &mut GarbageQueue,
+            &mut Hold,
+            &mut Streaks,
+            &mut Progress,
+        ),
+        (&mut Gravity, &mut Paused),
+    )>,
+) {
+    let saved = match resume.0.take() {
+        Some(saved) => saved,
+        None => return,
+    };
+    // the board size is in the settings, which may have changed since
+    let fits = saved
+        .players
+        .iter()
+        .flat_map(|player| &player.cells)
+        .all(|cell| {
+            (0..settings.board_width as i32).contains(&cell.pos[0])
+                && (0..settings.board_height as i32).contains(&cell.pos[1])
+        });
+    if !fits {
+        println!("the saved game doesn't fit on the board, not resuming it");
+        return;
+    }
+    for saved in saved.players {
+        let (player, _, mut board_state, queues, timers) = match players
+            .iter_mut()
+            .find(|(_, &Player { index }, ..)| index == saved.index)
+        {
+            Some(found) => found,
+            None => continue,
+        };
+        let (mut piece_queue, mut garbage_queue, mut hold, mut streaks, mut progress) = queues;
+        let (mut gravity, mut paused) = timers;
+
+        // whatever the mode started the board with goes
+        commands.entity(player).despawn_descendants();
+        *board_state = Board::new(board_state.width(), board_state.height());
+        let mut cells = Vec::new();
+        for cell in saved.cells {
+            let pos = IVec2::from(cell.pos);
+            let color = Color::from(cell.color);
+            let ent = spawn_cell(&mut commands, &skin, pos, cell.fill, color);
+            *board_state.cell_mut(pos) = Some(Cell {
+                ent,
+                fill: cell.fill,
+                color,
+            });
+            cells.push(ent);
+        }
+        commands.entity(player).push_children(&cells);
+
+        if let Some(piece) = saved.active {
+            spawn_block(
+                &mut commands,
+                &skin,
+                player,
+                piece.to_movable(),
+                Color::from(piece.color),
+            );
+        }
+        *piece_queue = PieceQueue::restore(saved.queue, &settings.pieces, settings.randomizer);
+        *garbage_queue = saved.garbage;
+        *hold = saved.hold;
+        *streaks = saved.streaks;
+        *progress = saved.progress;
+        gravity.0.set_elapsed(saved.gravity);
+        paused.0 = saved.paused;
+        if let Some(elapsed) = saved.skate {
+            let mut skate = Timer::new(settings.lock_delay(), false);
+            skate.set_elapsed(elapsed);
+            commands.entity(player).insert(SkateTimer(skate));
+        }
+    }
+    println!("resumed the saved game");
+}
+
+#[cfg(test)]
+mod test {
+    use bevy::math::IVec2;
+
+    use crate::tetris_block::{
+        menu::GameState,
+        movable_block::{BlockName, RotDir},
+        piece_queue::{PieceQueue, Randomizer},
+        Puzzle, Sprint, STANDARD_BLOCKS,
+    };
+
+    use super::{not_saving, SavedPiece};
+
+    #[test]
+    fn test_not_saving() {
+        let sprint = Sprint::default();
+        assert_eq!(not_saving(&GameState::Playing, &sprint, false), None);
+        assert_eq!(not_saving(&GameState::Paused, &sprint, false), None);
+        for state in [GameState::Menu, GameState::Settings, GameState::Controls] {
+            assert!(not_saving(&state, &sprint, false).is_some());
+        }
+        assert!(not_saving(&GameState::Playing, &sprint, true).is_some());
+
+        // a puzzle's save couldn't be resumed, so it's never written
+        let puzzle = Puzzle::parse("pieces: TT", 4, 4).unwrap();
+        assert_eq!(
+            not_saving(&GameState::Playing, &puzzle, false),
+            Some("puzzles can't be resumed")
+        );
+    }
+
+    #[test]
+    fn test_saved_piece() {
+        let mut movable = BlockName::T.create_movable(IVec2::new(3, 5));
+        for _ in 0..3 {
+            movable = movable.rotate(RotDir::Left).0;
+        }
+        let saved = SavedPiece {
+            name: movable.name(),
+            pos: movable.root_position().into(),
+            rotation: movable.rotation_state(),
+            color: [1., 0., 0., 1.],
+        };
+        let restored = saved.to_movable();
+        assert_eq!(restored.root_position(), IVec2::new(3, 5));
+        assert_eq!(
+            restored.positions().collect::<Vec<_>>(),
+            movable.positions().collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn test_saved_queue() {
+        let mut queue = PieceQueue::new(7, STANDARD_BLOCKS, Randomizer::Bag);
+        queue.pop();
+        let json = serde_json::to_string(&queue.save()).unwrap();
+        let mut restored = PieceQueue::restore(
+            serde_json::from_str(&json).unwrap(),
+            STANDARD_BLOCKS,
+            Randomizer::Bag,
+        );
+        for _ in 0..20 {
+            assert_eq!(restored.pop(), queue.pop());
+        }
+    }
+}
@@ -0,0 +1,242 @@
+// everything that can be tuned, read from `settings.json` in the config directory
+// at startup. anything left out of the file keeps its default. the file is written
+// back whenever the settings or controls are changed from the menu.
+
+use std::{fs, io, path::PathBuf, time::Duration};
+
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::GRID_CELLS;
+
+use super::{
+    controls::ControlsConfig,
+    delays::{ClearEffect, Delays},
+    env::STANDARD_BLOCKS,
+    movable_block::BlockName,
+    palette::Palette,
+    piece_queue::Randomizer,
+    skate_timer::LOCK_DELAY,
+    skin::GhostStyle,
+    tweening_position::{Animation, Easing},
+};
+
+const MIN_BOARD_SIZE: usize = 4;
+// the rows of a bit board are u32s
+const MAX_BOARD_WIDTH: usize = 32;
+const MAX_BOARD_HEIGHT: usize = 40;
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Settings {
+    // how long blocks take to drop a row, as a multiple of what the mode says
+    pub gravity_scale: f32,
+    // how long a block can sit on the stack before it's locked in place
+    pub lock_delay_ms: u64,
+    // holding a move repeats it after the delayed auto shift, then every auto repeat
+    // rate. an arr of 0 goes straight to the wall.
+    pub das_ms: u64,
+    pub arr_ms: u64,
+    // null snaps everything straight into place
+    pub animation: Option<Easing>,
+    pub line_clear_delay_ms: u64,
+    pub entry_delay_ms: u64,
+    pub clear_effect: ClearEffect,
+    pub board_width: usize,
+    pub board_height: usize,
+    pub pieces: Vec<BlockName>,
+    pub randomizer: Randomizer,
+    // what blocks are coloured with, one picked at random for each, as hex
+    pub colors: Vec<String>,
+    // the name of a png in `assets/skins`, or plain
+    pub skin: String,
+    pub ghost: GhostStyle,
+    // colours for each piece instead of `colors`, and marks to tell them apart by
+    pub palette: Palette,
+    pub symbols: bool,
+    pub controls: ControlsConfig,
+}
+impl Default for Settings {
+    fn default() -> Self {
+        let animation = Animation::default();
+        let delays = Delays::default();
+        Settings {
+            gravity_scale: 1.,
+            lock_delay_ms: LOCK_DELAY.as_millis() as u64,
+            das_ms: 170,
+            arr_ms: 50,
+            animation: animation.easing,
+            line_clear_delay_ms: delays.line_clear.as_millis() as u64,
+            entry_delay_ms: delays.entry.as_millis() as u64,
+            clear_effect: delays.clear_effect,
+            board_width: GRID_CELLS.width as usize,
+            board_height: GRID_CELLS.height as usize,
+            pieces: STANDARD_BLOCKS.to_vec(),
+            randomizer: Randomizer::Random,
+            colors: ["#ff0000", "#00ff00", "#0000ff", "#ffa500", "#800080"]
+                .iter()
+                .map(|hex| hex.to_string())
+                .collect(),
+            skin: "tile".to_string(),
+            ghost: GhostStyle::Faded,
+            palette: Palette::Random,
+            symbols: false,
+            controls: ControlsConfig::default(),
+        }
+    }
+}
+impl Settings {
+    fn path() -> Option<PathBuf> {
+        dirs::config_dir().map(|dir| dir.join("knockoff-tetris").join("settings.json"))
+    }
+
+    // the defaults if there's no file yet, but a file that can't be used is an error
+    // rather than being quietly ignored
+    pub fn load() -> Result<Settings, String> {
+        let path = match Self::path() {
+            Some(path) => path,
+            None => return Ok(Settings::default()),
+        };
+        match fs::read_to_string(&path) {
+            Ok(contents) => {
+                Settings::parse(&contents).map_err(|err| format!("{}: {}", path.display(), err))
+            }
+            Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(Settings::default()),
+            Err(err) => Err(format!("couldn't read {}: {}", path.display(), err)),
+        }
+    }
+
+    pub fn parse(contents: &str) -> Result<Settings, String> {
+        let settings: Settings = serde_json::from_str(contents).map_err(|err| err.to_string())?;
+        settings.validate()?;
+        Ok(settings)
+    }
+
+    pub fn save(&self) -> io::Result<()> {
+        let path = Self::path()
+            .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "no config directory"))?;
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        fs::write(path, serde_json::to_string_pretty(self).unwrap())
+    }
+
+    pub fn validate(&self) -> Result<(), String> {
+        if self.gravity_scale <= 0. || !self.gravity_scale.is_finite() {
+            return Err(format!(
+                "gravity_scale is {}, it has to be more than 0",
+                self.gravity_scale
+            ));
+        }
+        for (name, value, max) in [
+            ("board_width", self.board_width, MAX_BOARD_WIDTH),
+            ("board_height", self.board_height, MAX_BOARD_HEIGHT),
+        ] {
+            if !(MIN_BOARD_SIZE..=max).contains(&value) {
+                return Err(format!(
+                    "{} is {}, it has to be from {} to {}",
+                    name, value, MIN_BOARD_SIZE, max
+                ));
+            }
+        }
+        if self.pieces.is_empty() {
+            return Err(
+                r#"pieces is empty, it needs at least one of "I", "O", "T", "S", "Z", "J" or "L""#
+                    .to_string(),
+            );
+        }
+        if self.pieces.contains(&BlockName::Test) {
+            return Err("pieces has \"Test\", which is only for testing".to_string());
+        }
+        if self.skin.is_empty() || self.skin.contains(&['/', '\\', '.'][..]) {
+            return Err(format!(
+                "skin is {:?}, it has to be the name of a png in assets/skins, without the .png",
+                self.skin
+            ));
+        }
+        if self.colors.is_empty() {
+            return Err("colors is empty, it needs at least one".to_string());
+        }
+        for hex in &self.colors {
+            if parse_color(hex).is_none() {
+                return Err(format!(
+                    "colors has {:?}, which isn't a hex colour like \"#ff8800\"",
+                    hex
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    pub fn lock_delay(&self) -> Duration {
+        Duration::from_millis(self.lock_delay_ms)
+    }
+
+    pub fn colors(&self) -> Vec<Color> {
+        self.colors
+            .iter()
+            .filter_map(|hex| parse_color(hex))
+            .collect()
+    }
+
+    pub fn animation(&self) -> Animation {
+        Animation {
+            easing: self.animation,
+            ..default()
+        }
+    }
+
+    pub fn delays(&self) -> Delays {
+        Delays {
+            line_clear: Duration::from_millis(self.line_clear_delay_ms),
+            entry: Duration::from_millis(self.entry_delay_ms),
+            clear_effect: self.clear_effect,
+        }
+    }
+
+    // takes on the animation and delays changed from the settings screen
+    pub fn remember(&mut self, animation: &Animation, delays: &Delays) {
+        self.animation = animation.easing;
+        self.line_clear_delay_ms = delays.line_clear.as_millis() as u64;
+        self.entry_delay_ms = delays.entry.as_millis() as u64;
+        self.clear_effect = delays.clear_effect;
+    }
+}
+
+fn parse_color(hex: &str) -> Option<Color> {
+    Color::hex(hex.trim_start_matches('#')).ok()
+}
+
+#[cfg(test)]
+mod test {
+    use crate::tetris_block::movable_block::BlockName;
+
+    use super::Settings;
+
+    #[test]
+    fn test_parse() {
+        let settings = Settings::parse(r#"{ "das_ms": 100, "pieces": ["T", "I"] }"#).unwrap();
+        assert_eq!(settings.das_ms, 100);
+        assert_eq!(settings.pieces, [BlockName::T, BlockName::I]);
+        assert_eq!(settings.arr_ms, Settings::default().arr_ms);
+
+        let json = serde_json::to_string(&Settings::default()).unwrap();
+        assert_eq!(Settings::parse(&json).unwrap(), Settings::default());
+        assert_eq!(Settings::default().colors().len(), 5);
+    }
+
+    #[test]
+    fn test_errors() {
+        let err = |json| Settings::parse(json).unwrap_err();
+        assert_eq!(
+            err(r#"{ "board_width": 40 }"#),
+            "board_width is 40, it has to be from 4 to 32"
+        );
+        assert!(err(r#"{ "pieces": [] }"#).starts_with("pieces is empty"));
+        assert!(err(r#"{ "colors": ["blue"] }"#).contains("\"blue\""));
+        assert!(err(r#"{ "skin": "tile.png" }"#).starts_with("skin is \"tile.png\""));
+        assert!(err(r#"{ "gravity_scale": 0 }"#).starts_with("gravity_scale is 0"));
+        // a typo shouldn't be quietly ignored
+        assert!(err(r#"{ "das": 100 }"#).contains("unknown field `das`"));
+    }
+}
@@ -1,3 +1,10 @@
+use std::time::Duration;
+
 use bevy::prelude::*;
 
+// how long a block can sit on the stack before it's locked in place, unless the
+// settings say otherwise
+pub const LOCK_DELAY: Duration = Duration::from_secs(2);
+
+#[derive(Component)]
 pub struct SkateTimer(pub Timer);
@@ -0,0 +1,455 @@
+// how cells are drawn. a skin is a png in `assets/skins`, either a single square tile
+// which is tinted with each block's colour, or a strip of 8 tiles drawn as they are,
+// one for each piece in the order I O T S Z J L, then garbage. `plain` needs no image,
+// and draws cells as flat squares with a darker middle. with symbols on, each piece
+// gets a mark drawn over it, so they can be told apart without their colours.
+
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::CELL_SIDE_LEN;
+
+use super::{movable_block::BlockName, palette::Palette, settings::Settings, Fill};
+
+pub const PLAIN: &str = "plain";
+// where the asset server looks, under the assets folder
+const SKINS_DIR: &str = "skins";
+const STRIP_ORDER: [BlockName; 7] = [
+    BlockName::I,
+    BlockName::O,
+    BlockName::T,
+    BlockName::S,
+    BlockName::Z,
+    BlockName::J,
+    BlockName::L,
+];
+// the pieces, then garbage
+const STRIP_TILES: usize = STRIP_ORDER.len() + 1;
+const OUTLINE_WIDTH: f32 = 3.;
+// how far apart the squares of a symbol are, as a part of the cell
+const SYMBOL_SPACING: f32 = 0.22;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum GhostStyle {
+    // the block's tile, washed out
+    Faded,
+    // the block's tile, see-through
+    Translucent,
+    // just the edges of each cell
+    Outline,
+    Off,
+}
+impl GhostStyle {
+    pub const ALL: [GhostStyle; 4] = [
+        GhostStyle::Faded,
+        GhostStyle::Translucent,
+        GhostStyle::Outline,
+        GhostStyle::Off,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            GhostStyle::Faded => "faded",
+            GhostStyle::Translucent => "translucent",
+            GhostStyle::Outline => "outline",
+            GhostStyle::Off => "off",
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Look {
+    Plain,
+    Tile(Handle<Image>),
+    Strip(Handle<TextureAtlas>),
+}
+
+// what a skin's image turned out to be
+#[derive(Copy, Clone, Debug, PartialEq)]
+enum Kind {
+    Plain,
+    Tile,
+    // with the size of each tile
+    Strip(u32),
+}
+
+pub struct Skin {
+    name: String,
+    look: Look,
+    pub ghost: GhostStyle,
+    pub palette: Palette,
+    pub symbols: bool,
+}
+impl Default for Skin {
+    fn default() -> Self {
+        Skin {
+            name: PLAIN.to_string(),
+            look: Look::Plain,
+            ghost: GhostStyle::Faded,
+            palette: Palette::Random,
+            symbols: false,
+        }
+    }
+}
+impl Skin {
+    // the colour to draw a cell with, `color` unless the palette has its own.
+    // strips have their own colours.
+    pub(super) fn tint(&self, fill: Fill, color: Color) -> Color {
+        match self.look {
+            Look::Strip(_) => Color::WHITE,
+            _ => self.palette.color(fill).unwrap_or(color),
+        }
+    }
+
+    // one square of the skin, `size` across
+    pub(super) fn spawn_tile(
+        &self,
+        builder: &mut ChildBuilder,
+        fill: Fill,
+        color: Color,
+        size: f32,
+        translation: Vec3,
+    ) {
+        let transform = Transform::from_translation(translation);
+        let custom_size = Some(Vec2::splat(size));
+        match &self.look {
+            Look::Plain => {
+                builder.spawn_bundle(SpriteBundle {
+                    sprite: Sprite {
+                        color,
+                        custom_size,
+                        ..default()
+                    },
+                    transform,
+                    ..default()
+                });
+            }
+            Look::Tile(texture) => {
+                builder.spawn_bundle(SpriteBundle {
+                    sprite: Sprite {
+                        color,
+                        custom_size,
+                        ..default()
+                    },
+                    texture: texture.clone(),
+                    transform,
+                    ..default()
+                });
+            }
+            Look::Strip(atlas) => {
+                builder.spawn_bundle(SpriteSheetBundle {
+                    sprite: TextureAtlasSprite {
+                        index: strip_index(fill),
+                        color,
+                        custom_size,
+                        ..default()
+                    },
+                    texture_atlas: atlas.clone(),
+                    transform,
+                    ..default()
+                });
+            }
+        }
+    }
+
+    // a cell of a block or on the board
+    pub(super) fn spawn_cell(&self, builder: &mut ChildBuilder, fill: Fill, color: Color, z: f32) {
+        self.spawn_tile(builder, fill, color, CELL_SIDE_LEN, Vec3::new(0., 0., z));
+        if self.look == Look::Plain {
+            self.spawn_tile(
+                builder,
+                fill,
+                color * 0.5,
+                CELL_SIDE_LEN * 0.9,
+                Vec3::new(0., 0., z + 1.),
+            );
+        }
+        self.spawn_symbol(
+            builder,
+            fill,
+            color,
+            CELL_SIDE_LEN,
+            Vec3::new(0., 0., z + 2.),
+        );
+    }
+
+    // the piece's mark, dark on light colours and light on dark ones
+    pub(super) fn spawn_symbol(
+        &self,
+        builder: &mut ChildBuilder,
+        fill: Fill,
+        color: Color,
+        size: f32,
+        translation: Vec3,
+    ) {
+        let rows = match symbol(fill) {
+            Some(rows) if self.symbols => rows,
+            _ => return,
+        };
+        let [r, g, b, _] = color.as_linear_rgba_f32();
+        let luminance = 0.2126 * r + 0.7152 * g + 0.0722 * b;
+        let mark = if luminance > 0.2 {
+            Color::rgba(0., 0., 0., 0.7)
+        } else {
+            Color::rgba(1., 1., 1., 0.8)
+        };
+        let spacing = size * SYMBOL_SPACING;
+        for (row, line) in rows.iter().enumerate() {
+            for (col, _) in line.chars().enumerate().filter(|&(_, c)| c == '#') {
+                let offset = Vec3::new(col as f32 - 1., 1. - row as f32, 0.) * spacing;
+                builder.spawn_bundle(SpriteBundle {
+                    sprite: Sprite {
+                        color: mark,
+                        custom_size: Some(Vec2::splat(spacing)),
+                        ..default()
+                    },
+                    transform: Transform::from_translation(translation + offset),
+                    ..default()
+                });
+            }
+        }
+    }
+
+    // a cell of the ghost, showing where the block would land
+    pub(super) fn spawn_ghost_cell(
+        &self,
+        builder: &mut ChildBuilder,
+        fill: Fill,
+        color: Color,
+        z: f32,
+    ) {
+        let at = Vec3::new(0., 0., z);
+        match self.ghost {
+            GhostStyle::Faded => {
+                // desaturated and darker, so a strip's white still looks washed out
+                let faded = color.as_hsla() * Vec4::new(1., 0.2, 0.6, 1.);
+                self.spawn_tile(builder, fill, faded, CELL_SIDE_LEN, at);
+            }
+            GhostStyle::Translucent => {
+                let see_through = color * Vec4::new(1., 1., 1., 0.3);
+                self.spawn_tile(builder, fill, see_through, CELL_SIDE_LEN, at);
+            }
+            GhostStyle::Outline => {
+                let edge = (CELL_SIDE_LEN - OUTLINE_WIDTH) / 2.;
+                for (offset, size) in [
+                    (Vec2::new(0., edge), Vec2::new(CELL_SIDE_LEN, OUTLINE_WIDTH)),
+                    (
+                        Vec2::new(0., -edge),
+                        Vec2::new(CELL_SIDE_LEN, OUTLINE_WIDTH),
+                    ),
+                    (Vec2::new(edge, 0.), Vec2::new(OUTLINE_WIDTH, CELL_SIDE_LEN)),
+                    (
+                        Vec2::new(-edge, 0.),
+                        Vec2::new(OUTLINE_WIDTH, CELL_SIDE_LEN),
+                    ),
+                ] {
+                    builder.spawn_bundle(SpriteBundle {
+                        sprite: Sprite {
+                            color,
+                            custom_size: Some(size),
+                            ..default()
+                        },
+                        transform: Transform::from_translation(offset.extend(z)),
+                        ..default()
+                    });
+                }
+            }
+            GhostStyle::Off => {}
+        }
+    }
+}
+
+pub(super) struct SkinPlugin;
+impl Plugin for SkinPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<Skin>()
+            .insert_resource(SkinNames(skin_names(&skins_path())))
+            // before the boards are set up at startup
+            .add_startup_system_to_stage(StartupStage::PreStartup, update_skin)
+            .add_system_to_stage(CoreStage::PreUpdate, update_skin);
+    }
+}
+
+// the skins there are to choose from, `plain` first
+pub(super) struct SkinNames(pub Vec<String>);
+
+// each piece's mark, on a 3x3 grid from the top. mostly the piece's letter.
+fn symbol(fill: Fill) -> Option<[&'static str; 3]> {
+    match fill {
+        Fill::Piece(BlockName::I) => Some([".#.", ".#.", ".#."]),
+        Fill::Piece(BlockName::O) => Some(["###", "#.#", "###"]),
+        Fill::Piece(BlockName::T) => Some(["###", ".#.", ".#."]),
+        Fill::Piece(BlockName::S) => Some([".##", ".#.", "##."]),
+        Fill::Piece(BlockName::Z) => Some(["##.", ".#.", ".##"]),
+        Fill::Piece(BlockName::J) => Some(["..#", "..#", "###"]),
+        Fill::Piece(BlockName::L) => Some(["#..", "#..", "###"]),
+        Fill::Piece(BlockName::Test) | Fill::Garbage => None,
+    }
+}
+
+fn strip_index(fill: Fill) -> usize {
+    match fill {
+        Fill::Piece(name) => STRIP_ORDER
+            .iter()
+            .position(|&piece| piece == name)
+            .unwrap_or(STRIP_TILES - 1),
+        Fill::Garbage => STRIP_TILES - 1,
+    }
+}
+
+// the same folder the asset server reads from
+fn skins_path() -> PathBuf {
+    let root = match std::env::var("CARGO_MANIFEST_DIR") {
+        Ok(dir) => PathBuf::from(dir),
+        Err(_) => std::env::current_exe()
+            .ok()
+            .and_then(|exe| exe.parent().map(Path::to_path_buf))
+            .unwrap_or_default(),
+    };
+    root.join("assets").join(SKINS_DIR)
+}
+
+fn skin_names(dir: &Path) -> Vec<String> {
+    let mut names: Vec<String> = fs::read_dir(dir)
+        .into_iter()
+        .flatten()
+        .filter_map(|entry| {
+            let path = entry.ok()?.path();
+            if path.extension()? != "png" {
+                return None;
+            }
+            Some(path.file_stem()?.to_str()?.to_string())
+        })
+        .collect();
+    names.sort();
+    std::iter::once(PLAIN.to_string()).chain(names).collect()
+}
+
+// the width and height from a png's header
+fn png_size(bytes: &[u8]) -> Option<(u32, u32)> {
+    const SIGNATURE: &[u8] = b"\x89PNG\r\n\x1a\n";
+    if bytes.len() < 24 || &bytes[..8] != SIGNATURE || &bytes[12..16] != b"IHDR" {
+        return None;
+    }
+    let read = |at: usize| u32::from_be_bytes(bytes[at..at + 4].try_into().unwrap());
+    Some((read(16), read(20)))
+}
+
+fn skin_kind(width: u32, height: u32) -> Option<Kind> {
+    if width == 0 {
+        None
+    } else if width == height {
+        Some(Kind::Tile)
+    } else if width == height * STRIP_TILES as u32 {
+        Some(Kind::Strip(height))
+    } else {
+        None
+    }
+}
+
+fn load_kind(dir: &Path, name: &str) -> Result<Kind, String> {
+    if name == PLAIN {
+        return Ok(Kind::Plain);
+    }
+    let path = dir.join(format!("{}.png", name));
+    let bytes =
+        fs::read(&path).map_err(|err| format!("couldn't read {}: {}", path.display(), err))?;
+    let (width, height) =
+        png_size(&bytes).ok_or_else(|| format!("{} isn't a png", path.display()))?;
+    skin_kind(width, height).ok_or_else(|| {
+        format!(
+            "{} is {}x{}, it has to be a square tile or a strip of {} of them",
+            path.display(),
+            width,
+            height,
+            STRIP_TILES
+        )
+    })
+}
+
+// picks up the skin and how it's drawn from the settings, whenever they change
+fn update_skin(
+    settings: Res<Settings>,
+    mut skin: ResMut<Skin>,
+    asset_server: Option<Res<AssetServer>>,
+    atlases: Option<ResMut<Assets<TextureAtlas>>>,
+) {
+    if !settings.is_changed() {
+        return;
+    }
+    skin.ghost = settings.ghost;
+    skin.palette = settings.palette;
+    skin.symbols = settings.symbols;
+    if skin.name == settings.skin {
+        return;
+    }
+    // without anywhere to load images from, there's only plain
+    let (asset_server, mut atlases) = match (asset_server, atlases) {
+        (Some(asset_server), Some(atlases)) => (asset_server, atlases),
+        _ => return,
+    };
+    let asset_path = format!("{}/{}.png", SKINS_DIR, settings.skin);
+    skin.look = match load_kind(&skins_path(), &settings.skin) {
+        Ok(Kind::Plain) => Look::Plain,
+        Ok(Kind::Tile) => Look::Tile(asset_server.load(asset_path.as_str())),
+        Ok(Kind::Strip(size)) => Look::Strip(atlases.add(TextureAtlas::from_grid(
+            asset_server.load(asset_path.as_str()),
+            Vec2::splat(size as f32),
+            STRIP_TILES,
+            1,
+        ))),
+        Err(err) => {
+            println!("skin - {}, using {} instead", err, PLAIN);
+            Look::Plain
+        }
+    };
+    skin.name = settings.skin.clone();
+}
+
+#[cfg(test)]
+mod test {
+    use std::path::Path;
+
+    use crate::tetris_block::{movable_block::BlockName, Fill};
+
+    use super::{
+        load_kind, png_size, skin_kind, strip_index, symbol, Kind, STRIP_ORDER, STRIP_TILES,
+    };
+
+    #[test]
+    fn test_skin_kind() {
+        assert_eq!(skin_kind(32, 32), Some(Kind::Tile));
+        assert_eq!(skin_kind(256, 32), Some(Kind::Strip(32)));
+        assert_eq!(skin_kind(64, 32), None);
+        assert_eq!(skin_kind(0, 0), None);
+
+        assert_eq!(png_size(b"not a png at all, but long enough"), None);
+        let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("assets/skins");
+        assert_eq!(load_kind(&dir, "tile"), Ok(Kind::Tile));
+        assert_eq!(load_kind(&dir, "plain"), Ok(Kind::Plain));
+        assert!(load_kind(&dir, "missing").is_err());
+
+        assert_eq!(strip_index(Fill::Piece(BlockName::I)), 0);
+        assert_eq!(strip_index(Fill::Piece(BlockName::L)), 6);
+        assert_eq!(strip_index(Fill::Garbage), STRIP_TILES - 1);
+    }
+
+    #[test]
+    fn test_symbols() {
+        let symbols: Vec<_> = STRIP_ORDER
+            .iter()
+            .map(|&piece| symbol(Fill::Piece(piece)).unwrap())
+            .collect();
+        for (i, a) in symbols.iter().enumerate() {
+            assert!(a.iter().all(|row| row.len() == 3));
+            assert!(!symbols[i + 1..].contains(a), "{:?} is used twice", a);
+        }
+        assert_eq!(symbol(Fill::Garbage), None);
+    }
+}
@@ -0,0 +1,336 @@
+// publishes what's going on in the game over tcp, one json object per line, for
+// overlays and analysis tools to follow along without being part of the game.
+// anyone connecting is sent the current state of every board, then changes as they
+// happen.
+
+use std::{
+    collections::HashMap,
+    io::{self, Write},
+    net::{TcpListener, TcpStream},
+    sync::{mpsc, Mutex},
+    thread,
+    time::Duration,
+};
+
+use bevy::prelude::*;
+use serde::Serialize;
+
+use super::{
+    board::Board, garbage::OutgoingAttack, line_clear::LineClear, mode::Progress,
+    piece_queue::PieceQueue, BlockPlaced, Ghost, Hold, Player, TetrisBlock,
+};
+
+#[derive(Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum SpectatorMessage {
+    // a player's whole board, sent whenever anything on it changes
+    State(State),
+    // a player's score and clock, sent when they change, and every second while the
+    // clock is running
+    Progress(Stats),
+    Placed {
+        player: usize,
+        #[serde(flatten)]
+        clear: LineClear,
+    },
+    Garbage {
+        player: usize,
+        lines: u32,
+    },
+}
+
+#[derive(Serialize)]
+struct State {
+    player: usize,
+    // rows from the bottom up, '#' for a filled cell and '.' for an empty one
+    board: Vec<String>,
+    active: Option<ActivePiece>,
+    hold: Option<String>,
+    queue: Vec<String>,
+}
+
+// score, level, lines, pieces, whole seconds and whether the player has finished
+type StatsKey = (u32, u32, usize, usize, u64, bool);
+
+#[derive(Serialize)]
+struct Stats {
+    player: usize,
+    score: u32,
+    level: u32,
+    lines: usize,
+    pieces: usize,
+    time_ms: u64,
+    finished: bool,
+}
+impl Stats {
+    // what has to change for the stats to be sent again, the clock in whole seconds
+    fn key(&self) -> StatsKey {
+        (
+            self.score,
+            self.level,
+            self.lines,
+            self.pieces,
+            self.time_ms / 1000,
+            self.finished,
+        )
+    }
+}
+
+#[derive(Serialize)]
+struct ActivePiece {
+    piece: String,
+    cells: Vec<[i32; 2]>,
+}
+
+struct Spectators {
+    accepted: Mutex<mpsc::Receiver<TcpStream>>,
+    clients: Vec<TcpStream>,
+    // the last state sent for each player, to tell when it's changed
+    last: HashMap<usize, String>,
+    // and the last stats, with what they were sent for
+    last_stats: HashMap<usize, (StatsKey, String)>,
+}
+impl Spectators {
+    fn broadcast(&mut self, line: &str) {
+        self.clients
+            .retain_mut(|client| send_line(client, line).is_ok());
+    }
+}
+
+fn send_line(client: &mut TcpStream, line: &str) -> io::Result<()> {
+    writeln!(client, "{}", line)
+}
+
+pub struct SpectatePlugin {
+    pub port: u16,
+}
+impl Plugin for SpectatePlugin {
+    fn build(&self, app: &mut App) {
+        let listener = TcpListener::bind(("127.0.0.1", self.port))
+            .unwrap_or_else(|err| panic!("couldn't listen on port {}: {}", self.port, err));
+        println!("spectate - listening on port {}", self.port);
+        let (tx, rx) = mpsc::channel();
+        thread::spawn(move || {
+            for stream in listener.incoming() {
+                let stream = match stream {
+                    Ok(stream) => stream,
+                    Err(_) => continue,
+                };
+                // a spectator that stops reading gets dropped rather than holding
+                // up the game
+                let _ = stream.set_write_timeout(Some(Duration::from_millis(50)));
+                if tx.send(stream).is_err() {
+                    break;
+                }
+            }
+        });
+
+        app.insert_resource(Spectators {
+            accepted: Mutex::new(rx),
+            clients: Vec::new(),
+            last: HashMap::new(),
+            last_stats: HashMap::new(),
+        })
+        .add_system_to_stage(CoreStage::PostUpdate, publish_state);
+    }
+}
+
+fn publish_state(
+    mut spectators: ResMut<Spectators>,
+    mut placed: EventReader<BlockPlaced>,
+    mut attacks: EventReader<OutgoingAttack>,
+    players: Query<(Entity, &Player, &Board, &PieceQueue, &Hold, &Progress)>,
+    active_query: Query<(&Parent, &TetrisBlock), Without<Ghost>>,
+) {
+    let index_of = |player| players.get(player).map(|(_, p, ..)| p.index).ok();
+    let mut lines = Vec::new();
+    for &BlockPlaced { player, clear } in placed.iter() {
+        if let Some(player) = index_of(player) {
+            lines.push(SpectatorMessage::Placed { player, clear });
+        }
+    }
+    for attack in attacks.iter() {
+        if let Some(player) = index_of(attack.player) {
+            lines.push(SpectatorMessage::Garbage {
+                player,
+                lines: attack.lines,
+            });
+        }
+    }
+
+    for (player, &Player { index }, board_state, piece_queue, hold, progress) in players.iter() {
+        let active = active_query
+            .iter()
+            .find(|(parent, _)| parent.0 == player)
+            .map(|(_, block)| block);
+        let state = serde_json::to_string(&SpectatorMessage::State(state(
+            index,
+            board_state,
+            active,
+            hold,
+            piece_queue,
+        )))
+        .unwrap();
+        if spectators.last.get(&index) != Some(&state) {
+            spectators.broadcast(&state);
+            spectators.last.insert(index, state);
+        }
+
+        let stats = stats(index, progress);
+        let key = stats.key();
+        if spectators.last_stats.get(&index).map(|(last, _)| *last) != Some(key) {
+            let line = serde_json::to_string(&SpectatorMessage::Progress(stats)).unwrap();
+            spectators.broadcast(&line);
+            spectators.last_stats.insert(index, (key, line));
+        }
+    }
+    for line in lines {
+        spectators.broadcast(&serde_json::to_string(&line).unwrap());
+    }
+
+    // newcomers catch up on every board before getting changes
+    let accepted: Vec<_> = spectators.accepted.lock().unwrap().try_iter().collect();
+    for mut client in accepted {
+        let caught_up = spectators
+            .last
+            .values()
+            .chain(spectators.last_stats.values().map(|(_, line)| line))
+            .all(|line| send_line(&mut client, line).is_ok());
+        if caught_up {
+            println!("spectate - {:?} is watching", client.peer_addr());
+            spectators.clients.push(client);
+        }
+    }
+}
+
+fn stats(player: usize, progress: &Progress) -> Stats {
+    Stats {
+        player,
+        score: progress.score,
+        level: progress.level,
+        lines: progress.lines,
+        pieces: progress.pieces,
+        time_ms: progress.elapsed.as_millis() as u64,
+        finished: progress.outcome.is_some() || progress.topped_out,
+    }
+}
+
+fn state(
+    player: usize,
+    board: &Board,
+    active: Option<&TetrisBlock>,
+    hold: &Hold,
+    piece_queue: &PieceQueue,
+) -> State {
+    State {
+        player,
+        board: (0..board.height())
+            .map(|row| {
+                (0..board.width())
+                    .map(|col| match board.cell(IVec2::new(col as i32, row as i32)) {
+                        Some(_) => '#',
+                        None => '.',
+                    })
+                    .collect()
+            })
+            .collect(),
+        active: active.map(|block| ActivePiece {
+            piece: format!("{:?}", block.movable.name()),
+            cells: block
+                .movable
+                .positions()
+                .map(|pos| [pos.x, pos.y])
+                .collect(),
+        }),
+        hold: hold.piece.map(|name| format!("{:?}", name)),
+        queue: piece_queue
+            .upcoming()
+            .map(|name| format!("{:?}", name))
+            .collect(),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::Duration;
+
+    use bevy::prelude::*;
+
+    use crate::tetris_block::{
+        board::{Board, Cell},
+        line_clear::{LineClear, Spin},
+        mode::Progress,
+        movable_block::BlockName,
+        piece_queue::{PieceQueue, Randomizer},
+        Hold, TetrisBlock,
+    };
+
+    use super::{state, stats, SpectatorMessage};
+
+    #[test]
+    fn test_state_message() {
+        let mut board = Board::new(4, 3);
+        for col in 0..4 {
+            *board.cell_mut((col, 0).into()) = Some(Cell::garbage(Entity::from_raw(col as u32)));
+        }
+        let active = TetrisBlock {
+            movable: BlockName::O.create_movable((0, 1).into()),
+            color: Color::YELLOW,
+            last_move: None,
+        };
+        let hold = Hold {
+            piece: Some(BlockName::T),
+            ..default()
+        };
+        let queue = PieceQueue::new(0, &[BlockName::J], Randomizer::Random);
+
+        let message = SpectatorMessage::State(state(1, &board, Some(&active), &hold, &queue));
+        let json: serde_json::Value = serde_json::to_value(&message).unwrap();
+        assert_eq!(json["type"], "state");
+        assert_eq!(json["player"], 1);
+        assert_eq!(json["board"][0], "####");
+        assert_eq!(json["board"][1], "....");
+        assert_eq!(json["active"]["piece"], "O");
+        assert_eq!(json["active"]["cells"].as_array().unwrap().len(), 4);
+        assert_eq!(json["hold"], "T");
+        assert!(json["queue"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .all(|piece| piece == "J"));
+
+        let placed = SpectatorMessage::Placed {
+            player: 0,
+            clear: LineClear {
+                lines: 2,
+                spin: Spin::Full,
+                perfect_clear: false,
+                combo: 1,
+                back_to_back: true,
+            },
+        };
+        let json: serde_json::Value = serde_json::to_value(&placed).unwrap();
+        assert_eq!(json["type"], "placed");
+        assert_eq!(json["lines"], 2);
+        assert_eq!(json["spin"], "full");
+
+        let progress = Progress {
+            score: 1200,
+            level: 3,
+            lines: 24,
+            pieces: 30,
+            elapsed: Duration::from_millis(20_500),
+            ..default()
+        };
+        let stats = stats(0, &progress);
+        assert_eq!(stats.key().4, 20);
+        let json: serde_json::Value =
+            serde_json::to_value(&SpectatorMessage::Progress(stats)).unwrap();
+        assert_eq!(json["type"], "progress");
+        assert_eq!(json["score"], 1200);
+        assert_eq!(json["level"], 3);
+        assert_eq!(json["lines"], 24);
+        assert_eq!(json["time_ms"], 20_500);
+        assert_eq!(json["finished"], false);
+    }
+}
@@ -0,0 +1,534 @@
+// drives the game with an external bot speaking the tetris bot protocol
+// (https://github.com/tetris-bot-protocol/tbp-spec) over the bot's stdin/stdout
+
+use std::{
+    io::{self, BufRead, BufReader, Write},
+    process::{ChildStdin, Command, Stdio},
+    sync::{mpsc, Mutex},
+    thread,
+};
+
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use super::{
+    board::{Board, Fill},
+    handle_block_actions, last_player,
+    line_clear::Streaks,
+    movable_block::BlockName,
+    move_gen::path_to,
+    piece_queue::PieceQueue,
+    settings::Settings,
+    BlockAction, Ghost, Hold, PlayerAction, TetrisBlock,
+};
+
+// the board size the protocol uses, our board sits in the bottom left corner of it
+const TBP_WIDTH: usize = 10;
+const TBP_HEIGHT: usize = 40;
+
+#[derive(Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum FrontendMessage {
+    Rules {},
+    Start(Start),
+    Suggest,
+    Stop,
+    Quit,
+}
+
+#[derive(Serialize)]
+struct Start {
+    hold: Option<char>,
+    queue: Vec<char>,
+    combo: u32,
+    back_to_back: bool,
+    // rows from the bottom up, each cell is either empty or the piece (or 'G' for
+    // garbage) filling it
+    board: Vec<Vec<Option<char>>>,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum BotMessage {
+    Error {
+        reason: String,
+    },
+    Ready,
+    Info {
+        name: String,
+        version: String,
+        author: String,
+    },
+    Suggestion {
+        moves: Vec<TbpMove>,
+    },
+    #[serde(other)]
+    Unknown,
+}
+
+#[derive(Deserialize, Debug)]
+struct TbpMove {
+    location: PieceLocation,
+}
+
+#[derive(Deserialize, Debug)]
+struct PieceLocation {
+    #[serde(rename = "type")]
+    kind: char,
+    orientation: Orientation,
+    x: i32,
+    y: i32,
+}
+
+#[derive(Deserialize, Debug, Copy, Clone)]
+#[serde(rename_all = "snake_case")]
+enum Orientation {
+    North,
+    East,
+    South,
+    West,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+enum BotState {
+    // waiting for the bot to introduce itself
+    Starting,
+    WaitingReady,
+    Ready,
+    // the bot has given up, it's ignored from here on
+    Failed,
+}
+
+// on the player the bot plays as
+#[derive(Component)]
+pub struct TbpBot {
+    stdin: ChildStdin,
+    messages: Mutex<mpsc::Receiver<BotMessage>>,
+    state: BotState,
+    // a new active block has shown up, the bot has to be told about it
+    needs_start: bool,
+    started: bool,
+    // suggestions asked for but not received yet, only the latest one is acted on
+    outstanding: u32,
+    // the cells the active block should end up in
+    target: Option<Vec<IVec2>>,
+    move_timer: Timer,
+}
+impl TbpBot {
+    pub fn spawn(command: &[String]) -> io::Result<TbpBot> {
+        let (program, args) = command
+            .split_first()
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "no bot command given"))?;
+        let mut child = Command::new(program)
+            .args(args)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .spawn()?;
+
+        let stdin = child.stdin.take().unwrap();
+        let stdout = child.stdout.take().unwrap();
+        let (tx, rx) = mpsc::channel();
+        thread::spawn(move || {
+            for line in BufReader::new(stdout).lines() {
+                let line = match line {
+                    Ok(line) => line,
+                    Err(_) => break,
+                };
+                match serde_json::from_str(&line) {
+                    Ok(message) => {
+                        if tx.send(message).is_err() {
+                            break;
+                        }
+                    }
+                    Err(err) => println!("tbp - couldn't parse {:?}: {}", line, err),
+                }
+            }
+        });
+
+        Ok(TbpBot {
+            stdin,
+            messages: Mutex::new(rx),
+            state: BotState::Starting,
+            needs_start: false,
+            started: false,
+            outstanding: 0,
+            target: None,
+            move_timer: Timer::from_seconds(0.1, true),
+        })
+    }
+
+    fn send(&mut self, message: &FrontendMessage) {
+        let line = serde_json::to_string(message).unwrap();
+        if let Err(err) = writeln!(self.stdin, "{}", line) {
+            println!("tbp - bot went away: {}", err);
+            self.state = BotState::Failed;
+        }
+    }
+}
+impl Drop for TbpBot {
+    fn drop(&mut self) {
+        self.send(&FrontendMessage::Quit);
+    }
+}
+
+pub struct TbpPlugin {
+    // the bot's program, followed by its arguments
+    pub command: Vec<String>,
+}
+impl Plugin for TbpPlugin {
+    fn build(&self, app: &mut App) {
+        let settings = app.world.resource::<Settings>();
+        if let Err(err) = check_board_size(settings.board_width, settings.board_height) {
+            panic!("can't play with a tbp bot, {}", err);
+        }
+        let bot = TbpBot::spawn(&self.command)
+            .unwrap_or_else(|err| panic!("couldn't start tbp bot {:?}: {}", self.command, err));
+        let player = last_player(&mut app.world).expect("no player for the tbp bot");
+        app.world.entity_mut(player).insert(bot);
+        app.add_system_to_stage(
+            "update_block_positions",
+            run_tbp_bot.before(handle_block_actions),
+        );
+    }
+}
+
+fn run_tbp_bot(
+    time: Res<Time>,
+    mut players: Query<(Entity, &mut TbpBot, &Board, &PieceQueue, &Hold, &Streaks)>,
+    active_query: Query<(&Parent, &TetrisBlock), Without<Ghost>>,
+    new_block_query: Query<&Parent, (Added<TetrisBlock>, Without<Ghost>)>,
+    mut actions: EventWriter<PlayerAction>,
+) {
+    for (player, mut bot, board_state, piece_queue, hold, streaks) in players.iter_mut() {
+        let active = match active_query.iter().find(|(parent, _)| parent.0 == player) {
+            Some((_, block)) => block,
+            None => continue,
+        };
+        if new_block_query.iter().any(|parent| parent.0 == player) {
+            bot.needs_start = true;
+            bot.target = None;
+        }
+        let mut send = |action| actions.send(PlayerAction { player, action });
+
+        let messages: Vec<_> = bot.messages.lock().unwrap().try_iter().collect();
+        for message in messages {
+            match message {
+                BotMessage::Info {
+                    name,
+                    version,
+                    author,
+                } => {
+                    println!("tbp - playing with {} {} by {}", name, version, author);
+                    bot.send(&FrontendMessage::Rules {});
+                    bot.state = BotState::WaitingReady;
+                }
+                BotMessage::Ready => bot.state = BotState::Ready,
+                BotMessage::Error { reason } => {
+                    println!("tbp - bot refused to play: {}", reason);
+                    bot.state = BotState::Failed;
+                }
+                BotMessage::Suggestion { moves } => {
+                    bot.outstanding = bot.outstanding.saturating_sub(1);
+                    if bot.outstanding > 0 {
+                        continue;
+                    }
+                    if let Some(action) =
+                        pick_suggestion(&mut bot, &moves, board_state, active, hold, piece_queue)
+                    {
+                        send(action);
+                    }
+                }
+                BotMessage::Unknown => {}
+            }
+        }
+
+        if bot.state != BotState::Ready {
+            continue;
+        }
+
+        if bot.needs_start {
+            // rather than keeping the bot's idea of the game in sync move by move, it's
+            // given the whole state again for each block. anything that happens outside
+            // of the bot's control (gravity, the player, line clears) can't desync it.
+            bot.needs_start = false;
+            if bot.started {
+                bot.send(&FrontendMessage::Stop);
+            }
+            match start_message(board_state, active, hold, streaks, piece_queue) {
+                Some(start) => {
+                    bot.send(&FrontendMessage::Start(start));
+                    bot.send(&FrontendMessage::Suggest);
+                    bot.started = true;
+                    bot.outstanding += 1;
+                }
+                None => println!("tbp - {:?} can't be sent to the bot", active.movable.name()),
+            }
+            continue;
+        }
+
+        if !bot.move_timer.tick(time.delta()).just_finished() {
+            continue;
+        }
+        let target = match &bot.target {
+            Some(target) => target,
+            None => continue,
+        };
+        match path_to(board_state, &active.movable, target) {
+            Some(placement) => send(match placement.moves.first() {
+                Some(&mv) => BlockAction::Move(mv),
+                None => BlockAction::HardDrop,
+            }),
+            // the block was moved out from under the bot, ask it again
+            None => bot.needs_start = true,
+        }
+    }
+}
+
+// finds the first suggested move that can be made under our rules. returns an action
+// to send straight away when the move is to hold (or when nothing the bot suggested
+// works, in which case the block is just dropped).
+fn pick_suggestion(
+    bot: &mut TbpBot,
+    moves: &[TbpMove],
+    board: &Board,
+    active: &TetrisBlock,
+    hold: &Hold,
+    piece_queue: &PieceQueue,
+) -> Option<BlockAction> {
+    let active_piece = tbp_piece(active.movable.name());
+    let hold_piece = hold
+        .piece
+        .or_else(|| piece_queue.upcoming().next())
+        .and_then(tbp_piece);
+
+    for mv in moves {
+        let location = &mv.location;
+        if Some(location.kind) == active_piece {
+            let cells = match tbp_cells(location) {
+                Some(cells) => cells,
+                None => continue,
+            };
+            if path_to(board, &active.movable, &cells).is_some() {
+                bot.target = Some(cells);
+                return None;
+            }
+        } else if Some(location.kind) == hold_piece && !hold.used {
+            return Some(BlockAction::Hold);
+        }
+    }
+
+    println!("tbp - none of the bot's {} moves can be made", moves.len());
+    Some(BlockAction::HardDrop)
+}
+
+fn start_message(
+    board: &Board,
+    active: &TetrisBlock,
+    hold: &Hold,
+    streaks: &Streaks,
+    piece_queue: &PieceQueue,
+) -> Option<Start> {
+    let mut queue = vec![tbp_piece(active.movable.name())?];
+    queue.extend(piece_queue.upcoming().filter_map(tbp_piece));
+
+    Some(Start {
+        hold: hold.piece.and_then(tbp_piece),
+        queue,
+        combo: streaks.combo,
+        back_to_back: streaks.back_to_back,
+        board: tbp_board(board),
+    })
+}
+
+// the board has to fit in the protocol's, or the bot would plan against part of it
+fn check_board_size(width: usize, height: usize) -> Result<(), String> {
+    if width > TBP_WIDTH || height > TBP_HEIGHT {
+        Err(format!(
+            "the board is {}x{}, it has to fit in the protocol's {}x{}",
+            width, height, TBP_WIDTH, TBP_HEIGHT
+        ))
+    } else {
+        Ok(())
+    }
+}
+
+// columns past the edge of our (narrower) board are walled off with garbage
+fn tbp_board(board: &Board) -> Vec<Vec<Option<char>>> {
+    (0..TBP_HEIGHT)
+        .map(|row| {
+            (0..TBP_WIDTH)
+                .map(|col| {
+                    if col >= board.width() {
+                        Some('G')
+                    } else if row < board.height() {
+                        board
+                            .cell(IVec2::new(col as i32, row as i32))
+                            .map(|cell| match cell.fill {
+                                Fill::Piece(name) => tbp_piece(name).unwrap_or('G'),
+                                Fill::Garbage => 'G',
+                            })
+                    } else {
+                        None
+                    }
+                })
+                .collect()
+        })
+        .collect()
+}
+
+fn tbp_piece(name: BlockName) -> Option<char> {
+    match name {
+        BlockName::L => Some('L'),
+        BlockName::J => Some('J'),
+        BlockName::O => Some('O'),
+        BlockName::I => Some('I'),
+        BlockName::T => Some('T'),
+        BlockName::S => Some('S'),
+        BlockName::Z => Some('Z'),
+        BlockName::Test => None,
+    }
+}
+
+// the cells a piece covers, in the protocol every piece rotates clockwise around the
+// cell at its location
+fn tbp_cells(location: &PieceLocation) -> Option<Vec<IVec2>> {
+    let north: &[(i32, i32)] = match location.kind {
+        'I' => &[(-1, 0), (0, 0), (1, 0), (2, 0)],
+        'O' => &[(0, 0), (1, 0), (0, 1), (1, 1)],
+        'T' => &[(-1, 0), (0, 0), (1, 0), (0, 1)],
+        'L' => &[(-1, 0), (0, 0), (1, 0), (1, 1)],
+        'J' => &[(-1, 0), (0, 0), (1, 0), (-1, 1)],
+        'S' => &[(-1, 0), (0, 0), (0, 1), (1, 1)],
+        'Z' => &[(-1, 1), (0, 1), (0, 0), (1, 0)],
+        _ => return None,
+    };
+    let turns = location.orientation as usize;
+
+    let mut cells: Vec<IVec2> = north
+        .iter()
+        .map(|&(x, y)| {
+            let mut cell = IVec2::new(x, y);
+            for _ in 0..turns {
+                cell = IVec2::new(cell.y, -cell.x);
+            }
+            cell + IVec2::new(location.x, location.y)
+        })
+        .collect();
+    cells.sort_by_key(|pos| (pos.y, pos.x));
+    Some(cells)
+}
+
+#[cfg(test)]
+mod test {
+    use bevy::{ecs::entity::Entity, math::IVec2, render::color::Color};
+
+    use crate::tetris_block::{
+        board::{Board, Cell, Fill},
+        movable_block::{BlockName, RotDir},
+        move_gen::sorted_cells,
+    };
+
+    use super::{check_board_size, tbp_board, tbp_cells, BotMessage, Orientation, PieceLocation};
+
+    #[test]
+    fn test_check_board_size() {
+        assert!(check_board_size(8, 12).is_ok());
+        assert!(check_board_size(10, 40).is_ok());
+        assert_eq!(
+            check_board_size(11, 20),
+            Err("the board is 11x20, it has to fit in the protocol's 10x40".to_string())
+        );
+        assert!(check_board_size(10, 41).is_err());
+    }
+
+    #[test]
+    fn test_cells_match_block_shapes() {
+        let orientations = [
+            Orientation::North,
+            Orientation::East,
+            Orientation::South,
+            Orientation::West,
+        ];
+        for name in [
+            BlockName::L,
+            BlockName::J,
+            BlockName::O,
+            BlockName::I,
+            BlockName::T,
+            BlockName::S,
+            BlockName::Z,
+        ] {
+            let mut block = name.create_movable((0, 0).into());
+            for orientation in orientations {
+                let location = PieceLocation {
+                    kind: format!("{:?}", name).chars().next().unwrap(),
+                    orientation,
+                    x: 0,
+                    y: 0,
+                };
+                let cells = tbp_cells(&location).unwrap();
+
+                // same shape, give or take where the piece's origin is
+                let ours = sorted_cells(&block);
+                let offset = cells[0] - ours[0];
+                let shifted: Vec<IVec2> = ours.iter().map(|&pos| pos + offset).collect();
+                assert_eq!(shifted, cells, "{:?} {:?}", name, orientation);
+
+                block = block.rotate(RotDir::Right).0;
+            }
+        }
+    }
+
+    #[test]
+    fn test_board() {
+        let mut board = Board::new(8, 12);
+        *board.cell_mut((1, 0).into()) = Some(Cell::garbage(Entity::from_raw(0)));
+        *board.cell_mut((2, 0).into()) = Some(Cell {
+            ent: Entity::from_raw(1),
+            fill: Fill::Piece(BlockName::S),
+            color: Color::GREEN,
+        });
+
+        let rows = tbp_board(&board);
+        assert_eq!(rows.len(), 40);
+        assert_eq!(
+            rows[0],
+            vec![
+                None,
+                Some('G'),
+                Some('S'),
+                None,
+                None,
+                None,
+                None,
+                None,
+                Some('G'),
+                Some('G')
+            ]
+        );
+        assert!(rows[39][..8].iter().all(|cell| cell.is_none()));
+    }
+
+    #[test]
+    fn test_parse_suggestion() {
+        let message: BotMessage = serde_json::from_str(
+            r#"{"type":"suggestion","moves":[{"location":{"type":"T","orientation":"east","x":4,"y":1},"spin":"none"}]}"#,
+        )
+        .unwrap();
+        match message {
+            BotMessage::Suggestion { moves } => {
+                let cells = tbp_cells(&moves[0].location).unwrap();
+                assert_eq!(
+                    cells,
+                    vec![
+                        IVec2::new(4, 0),
+                        IVec2::new(4, 1),
+                        IVec2::new(5, 1),
+                        IVec2::new(4, 2)
+                    ]
+                );
+            }
+            other => panic!("unexpected message {:?}", other),
+        }
+    }
+}
@@ -1,25 +1,74 @@
-use std::f32::consts::TAU;
-
 use bevy::{
     core::Time,
-    math::{IVec2, Quat, Vec3},
-    prelude::{default, Component, Plugin, Query, Res, Transform},
+    prelude::{Component, Plugin, Query, Res, Transform},
 };
+use serde::{Deserialize, Serialize};
 
-use crate::{CELL_SIDE_LEN, GRID_CELLS};
+// how blocks and cells get from one position to the next
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Easing {
+    Linear,
+    // fast, then slowing down into place
+    EaseOut,
+    // slow at both ends
+    EaseInOut,
+}
+impl Easing {
+    pub fn from_name(name: &str) -> Option<Easing> {
+        match name {
+            "linear" => Some(Easing::Linear),
+            "ease-out" => Some(Easing::EaseOut),
+            "ease-in-out" => Some(Easing::EaseInOut),
+            _ => None,
+        }
+    }
 
-use super::movable_block::MovableBlock;
+    // maps how far through the movement the time is to how far along it the block is
+    fn apply(self, t: f32) -> f32 {
+        match self {
+            Easing::Linear => t,
+            Easing::EaseOut => 1. - (1. - t) * (1. - t),
+            Easing::EaseInOut => t * t * (3. - 2. * t),
+        }
+    }
+}
+
+// `easing: None` snaps everything straight into place, for competitive play where
+// the board on screen shouldn't lag behind the real one
+pub struct Animation {
+    pub easing: Option<Easing>,
+    // seconds
+    pub duration: f32,
+}
+impl Default for Animation {
+    fn default() -> Self {
+        Animation {
+            easing: Some(Easing::EaseOut),
+            duration: 0.08,
+        }
+    }
+}
 
 pub struct TweeningPositionPlugin;
 impl Plugin for TweeningPositionPlugin {
     fn build(&self, app: &mut bevy::prelude::App) {
-        app.add_system(tick_cell_position_times);
+        app.init_resource::<Animation>()
+            .add_system_to_stage("update_block_transforms", tick_cell_position_times);
     }
 }
 
-fn tick_cell_position_times(mut cell_positions: Query<&mut TweeningTransform>, time: Res<Time>) {
-    for mut cp in cell_positions.iter_mut() {
+pub(super) fn tick_cell_position_times(
+    mut cell_positions: Query<(&mut Transform, &mut TweeningTransform)>,
+    time: Res<Time>,
+) {
+    for (mut t, mut cp) in cell_positions.iter_mut() {
+        // the transform is left alone once it's there, so it doesn't look changed
+        if cp.done() {
+            continue;
+        }
         cp.tick(time.delta_seconds());
+        *t = cp.transform();
     }
 }
 
@@ -29,27 +78,31 @@ pub struct TweeningTransform {
     target: Transform,
     elapsed: f32,
     duration: f32,
+    easing: Easing,
 }
 
 impl TweeningTransform {
-    pub fn new(transform: &Transform, duration: f32) -> TweeningTransform {
+    pub fn new(transform: &Transform, duration: f32, easing: Easing) -> TweeningTransform {
         TweeningTransform {
             start: *transform,
             target: *transform,
-            elapsed: 0.,
+            elapsed: duration,
             duration,
+            easing,
         }
     }
 
     pub fn transform(&self) -> Transform {
-        let t = (self.elapsed / self.duration).clamp(0., 1.);
-
-        // xxx - play with different interpolation curves
-        // let t = t * t;
+        let t = if self.duration > 0. {
+            self.easing
+                .apply((self.elapsed / self.duration).clamp(0., 1.))
+        } else {
+            1.
+        };
 
         Transform {
             translation: self.start.translation.lerp(self.target.translation, t),
-            rotation: self.start.rotation.lerp(self.target.rotation, t),
+            rotation: self.start.rotation.slerp(self.target.rotation, t),
             scale: self.start.scale.lerp(self.target.scale, t),
         }
     }
@@ -62,7 +115,46 @@ impl TweeningTransform {
         }
     }
 
+    fn done(&self) -> bool {
+        self.elapsed >= self.duration
+    }
+
     fn tick(&mut self, dur: f32) {
         self.elapsed += dur;
     }
 }
+
+#[cfg(test)]
+mod test {
+    use bevy::{math::Vec3, prelude::Transform};
+
+    use super::{Easing, TweeningTransform};
+
+    #[test]
+    fn test_easing() {
+        for easing in [Easing::Linear, Easing::EaseOut, Easing::EaseInOut] {
+            assert_eq!(easing.apply(0.), 0.);
+            assert_eq!(easing.apply(1.), 1.);
+        }
+        assert!(Easing::EaseOut.apply(0.5) > 0.5);
+        assert_eq!(Easing::EaseInOut.apply(0.5), 0.5);
+    }
+
+    #[test]
+    fn test_tween() {
+        let mut tween = TweeningTransform::new(&Transform::identity(), 1., Easing::Linear);
+        assert!(tween.done());
+
+        tween.set_target(&Transform::from_xyz(10., 0., 0.));
+        assert_eq!(tween.transform().translation, Vec3::ZERO);
+        tween.tick(0.5);
+        assert_eq!(tween.transform().translation, Vec3::new(5., 0., 0.));
+
+        // retargeting part way carries on from where it got to
+        tween.set_target(&Transform::from_xyz(5., 10., 0.));
+        assert_eq!(tween.transform().translation, Vec3::new(5., 0., 0.));
+        tween.tick(1.);
+        assert!(tween.done());
+        assert_eq!(tween.transform().translation, Vec3::new(5., 10., 0.));
+    }
+}